@@ -0,0 +1,94 @@
+// Enhancement passes with the packed-row kernel against the per-pixel get_index code it
+// replaced. Run with `cargo bench --bench day20`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use aoc2021_rust::day20::{input_generator, InfiniteImage};
+
+const EXAMPLE: &str = "..#.#..#####.#.#.#.###.##.....###.##.#..###.####..#####..#....#..#..##..###..######.###...####..#..#####..##..#.#####...##.#.#..#.##..#.#......#.###.######.###.####...#.##.##..#..#..#####.....#.#....###..#.##......#.....#..#..#..##..#...##.######.####.####.#.#...#.......#..#.#.#...####.##.#......#..#...##.#.##..#...##.#.##..###.#......#.#.......#.#.#.####.###.##...#.....####.#..#..#.##.#....##..#.####....##...##..#...#......#.#.......#.......##..####..#...#.#.#...##..#.#..###..#####........#..####......#..#
+
+#..#.
+#....
+##..#
+..#..
+..###";
+
+// The original solver: pad the grid by one pixel of background, then look up each pixel's
+// nine neighbours through Option chains
+fn get_index(grid: &[Vec<bool>], pos: (usize, usize), empty: bool) -> usize {
+    let (y, x) = pos;
+    let bin = [
+        y.checked_sub(1)
+            .map(|ym| x.checked_sub(1).map(|xm| grid[ym][xm]).unwrap_or(empty))
+            .unwrap_or(empty),
+        y.checked_sub(1).map(|ym| grid[ym][x]).unwrap_or(empty),
+        y.checked_sub(1)
+            .map(|ym| grid[ym].get(x + 1).copied().unwrap_or(empty))
+            .unwrap_or(empty),
+        x.checked_sub(1).map(|xm| grid[y][xm]).unwrap_or(empty),
+        grid[y][x],
+        grid[y].get(x + 1).copied().unwrap_or(empty),
+        grid.get(y + 1)
+            .map(|yp| x.checked_sub(1).map(|xm| yp[xm]).unwrap_or(empty))
+            .unwrap_or(empty),
+        grid.get(y + 1).map(|yp| yp[x]).unwrap_or(empty),
+        grid.get(y + 1)
+            .map(|yp| yp.get(x + 1).copied().unwrap_or(empty))
+            .unwrap_or(empty),
+    ];
+    bin.iter().fold(0, |out, b| out << 1 | *b as usize)
+}
+
+fn enhance_get_index(ie: &[bool], grid: &[Vec<bool>], passes: usize) -> Vec<Vec<bool>> {
+    let mut base_image = grid.to_vec();
+    for i in 0..passes {
+        let empty = ie[0] && (i % 2 == 1);
+        let width = base_image[0].len() + 2;
+        let mut new_image = vec![vec![empty; width]];
+        for line in base_image.iter() {
+            let mut l = Vec::with_capacity(width);
+            l.push(empty);
+            l.extend(line);
+            l.push(empty);
+            new_image.push(l);
+        }
+        new_image.push(vec![empty; width]);
+
+        base_image = (0..new_image.len())
+            .map(|y| {
+                (0..width)
+                    .map(|x| ie[get_index(&new_image, (y, x), empty)])
+                    .collect()
+            })
+            .collect();
+    }
+    base_image
+}
+
+fn time<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let out = black_box(f());
+    (out, start.elapsed())
+}
+
+fn main() {
+    let (ie, small) = input_generator(EXAMPLE);
+    // 100x100 start image, like the real input
+    let grid: Vec<Vec<bool>> = (0..100)
+        .map(|y| (0..100).map(|x| small[y % 5][x % 5]).collect())
+        .collect();
+
+    for passes in [50, 300] {
+        let (kernel, kernel_time) = time(|| InfiniteImage::new(&ie, &grid).enhance(passes));
+        let (original, original_time) = time(|| enhance_get_index(&ie, &grid, passes));
+        let lit = original.iter().flatten().filter(|p| **p).count();
+        assert_eq!(kernel.lit(), Some(lit));
+        println!(
+            "{} passes on 100x100: kernel {:?}, get_index {:?} ({:.1}x)",
+            passes,
+            kernel_time,
+            original_time,
+            original_time.as_secs_f64() / kernel_time.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,24 @@
+// Burrow search on the example for both parts. Run with `cargo bench --bench day23`.
+use std::hint::black_box;
+use std::time::Instant;
+
+use aoc2021_rust::day23::{input_generator, solve_part1, solve_part2};
+
+const EXAMPLE: &str = "#############
+#...........#
+###B#C#B#D###
+  #A#D#C#A#
+  #########";
+
+fn main() {
+    let state = input_generator(EXAMPLE);
+    let start = Instant::now();
+    assert_eq!(black_box(solve_part1(&state)), 12521);
+    let part1 = start.elapsed();
+    assert_eq!(black_box(solve_part2(&state)), 44169);
+    println!(
+        "day23 example: part 1 {:?}, part 2 {:?}",
+        part1,
+        start.elapsed() - part1
+    );
+}
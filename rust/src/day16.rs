@@ -2,421 +2,1089 @@ use std::collections::{HashMap, HashSet};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 use lazy_static::lazy_static;
+use std::fmt;
 
-#[aoc_generator(day16)]
-pub fn input_generator(input: &str) -> Vec<bool> {
-    input
-        .chars()
-        .flat_map(|c| to_bits(c.to_digit(16).unwrap() as u8))
-        .collect()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    // Position in the hex string and the char found
+    InvalidHex(usize, char),
+    // Bit position of a read past the end of the transmission
+    Truncated(usize),
+    // Bit position of a set bit after the outermost packet
+    BadPadding(usize),
+    // Bit position of a literal too large for a usize
+    LiteralTooLarge(usize),
+    // Bit position where subpackets ran past their operator's total length
+    LengthMismatch(usize),
+    // Bit position of an operator, its type ID and how many subpackets it has
+    Arity(usize, u8, usize),
 }
 
-pub fn to_bits(x: u8) -> Vec<bool> {
-    let mut out: Vec<bool> = Vec::with_capacity(4);
-    for z in (0..4).rev() {
-        out.push(to_bool(((1 << z) & x) >> z));
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidHex(pos, c) => write!(f, "invalid hex digit {:?} at {}", c, pos),
+            Self::Truncated(pos) => write!(f, "transmission ends early at bit {}", pos),
+            Self::BadPadding(pos) => write!(f, "non-zero padding at bit {}", pos),
+            Self::LiteralTooLarge(pos) => write!(f, "literal at bit {} is too large", pos),
+            Self::LengthMismatch(pos) => {
+                write!(f, "subpackets overrun their total length at bit {}", pos)
+            }
+            Self::Arity(pos, t, n) => write!(
+                f,
+                "{} at bit {} can't have {} subpackets",
+                type_name(*t),
+                pos,
+                n
+            ),
+        }
     }
-    out
 }
 
-pub fn to_bool(x: u8) -> bool {
-    x > 0
+impl std::error::Error for DecodeError {}
+
+pub fn hex_to_bytes(input: &str) -> Result<Vec<u8>, DecodeError> {
+    let digits = input
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            c.to_digit(16)
+                .map(|d| d as u8)
+                .ok_or(DecodeError::InvalidHex(i, c))
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+    // An odd number of digits gets a zero nibble, which is read as padding
+    Ok(digits
+        .chunks(2)
+        .map(|c| c[0] << 4 | c.get(1).unwrap_or(&0))
+        .collect())
 }
-pub fn to_u8(x: bool) -> u8 {
-    if x {
-        1
-    } else {
-        0
-    }
+
+// Reads big-endian bit fields from a byte slice
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
 }
 
-pub fn from_bits(x: &[bool]) -> u8 {
-    let mut out: u8 = 0;
-    for z in 0..x.len() {
-        out += to_u8(x[z]) * (1 << (x.len() - z - 1));
+impl<'a> BitReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        BitReader { bytes, pos: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() * 8 - self.pos
     }
-    out
-}
 
-pub fn from_bits_u16(x: &[bool]) -> u16 {
-    let mut out: u16 = 0;
-    for z in 0..x.len() {
-        out += (to_u8(x[z]) as u16) * (1 << (x.len() - z - 1));
+    pub fn read_bit(&mut self) -> Result<bool, DecodeError> {
+        let byte = self
+            .bytes
+            .get(self.pos / 8)
+            .ok_or(DecodeError::Truncated(self.pos))?;
+        let bit = byte >> (7 - self.pos % 8) & 1 == 1;
+        self.pos += 1;
+        Ok(bit)
     }
-    out
+
+    // Up to 64 bits, most significant first
+    pub fn read(&mut self, bits: usize) -> Result<u64, DecodeError> {
+        assert!(bits <= 64, "can't read {} bits at once", bits);
+        if bits > self.remaining() {
+            return Err(DecodeError::Truncated(self.pos));
+        }
+        let mut out = 0;
+        for _ in 0..bits {
+            out = out << 1 | self.read_bit()? as u64;
+        }
+        Ok(out)
+    }
+
+    // Everything left must be zero
+    pub fn finish(&mut self) -> Result<(), DecodeError> {
+        while self.remaining() > 0 {
+            let pos = self.pos;
+            if self.read_bit()? {
+                return Err(DecodeError::BadPadding(pos));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[aoc_generator(day16)]
+pub fn input_generator(input: &str) -> Vec<u8> {
+    hex_to_bytes(input.trim()).expect("invalid hex transmission")
 }
 
-pub fn from_bin_str(x: &str) -> Vec<bool> {
-    x.chars()
-        .map(|c| if c == '0' { false } else { true })
-        .collect()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operands {
+    // false gives the subpackets' total length in bits, true gives their number
+    pub length_type: bool,
+    pub subpackets: Vec<Packet>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PacketKind {
+    Literal(usize),
+    Sum(Operands),
+    Product(Operands),
+    Min(Operands),
+    Max(Operands),
+    Gt(Operands),
+    Lt(Operands),
+    Eq(Operands),
+}
+
+impl PacketKind {
+    // None for the literal type and unassigned IDs
+    pub fn operator(packet_type: u8, operands: Operands) -> Option<PacketKind> {
+        use PacketKind::*;
+        Some(match packet_type {
+            0 => Sum(operands),
+            1 => Product(operands),
+            2 => Min(operands),
+            3 => Max(operands),
+            5 => Gt(operands),
+            6 => Lt(operands),
+            7 => Eq(operands),
+            _ => return None,
+        })
+    }
+
+    pub fn type_id(&self) -> u8 {
+        use PacketKind::*;
+        match self {
+            Sum(_) => 0,
+            Product(_) => 1,
+            Min(_) => 2,
+            Max(_) => 3,
+            Literal(_) => 4,
+            Gt(_) => 5,
+            Lt(_) => 6,
+            Eq(_) => 7,
+        }
+    }
+
+    pub fn operands(&self) -> Option<&Operands> {
+        use PacketKind::*;
+        match self {
+            Literal(_) => None,
+            Sum(o) | Product(o) | Min(o) | Max(o) | Gt(o) | Lt(o) | Eq(o) => Some(o),
+        }
+    }
+
+    // Comparisons take exactly two operands, everything else at least one
+    pub fn arity_ok(&self) -> bool {
+        use PacketKind::*;
+        match self {
+            Literal(_) => true,
+            Sum(o) | Product(o) | Min(o) | Max(o) => !o.subpackets.is_empty(),
+            Gt(o) | Lt(o) | Eq(o) => o.subpackets.len() == 2,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Packet {
     version: u8,
-    packet_type: u8,
-    value: Option<usize>,
-    length_type: Option<bool>,
-    length_subpackets: Option<u16>,
-    subpackets: Option<Vec<Packet>>,
+    kind: PacketKind,
+}
+
+impl Packet {
+    pub fn literal(version: u8, value: usize) -> Packet {
+        Packet {
+            version,
+            kind: PacketKind::Literal(value),
+        }
+    }
+
+    // None if packet_type isn't an operator. Any number of subpackets is accepted here: decode
+    // and compile reject the wrong arity, and evaluate reports it for packets built directly.
+    pub fn operator(
+        version: u8,
+        packet_type: u8,
+        length_type: bool,
+        subpackets: Vec<Packet>,
+    ) -> Option<Packet> {
+        let operands = Operands {
+            length_type,
+            subpackets,
+        };
+        let kind = PacketKind::operator(packet_type, operands)?;
+        Some(Packet { version, kind })
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn kind(&self) -> &PacketKind {
+        &self.kind
+    }
+
+    pub fn subpackets(&self) -> &[Packet] {
+        self.kind
+            .operands()
+            .map_or(&[], |o| o.subpackets.as_slice())
+    }
+
+    // Encoded length in bits, without padding
+    pub fn bit_len(&self) -> usize {
+        match &self.kind {
+            PacketKind::Literal(v) => 6 + 5 * literal_groups(*v),
+            kind => {
+                let o = kind.operands().unwrap();
+                let field = if o.length_type { 11 } else { 15 };
+                7 + field + o.subpackets.iter().map(|p| p.bit_len()).sum::<usize>()
+            }
+        }
+    }
+
+    // Value of the length field: total bits or number of subpackets
+    pub fn length_field(&self) -> Option<usize> {
+        self.kind.operands().map(|o| {
+            if o.length_type {
+                o.subpackets.len()
+            } else {
+                o.subpackets.iter().map(|p| p.bit_len()).sum()
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalError {
+    Overflow,
+    // Type ID and number of subpackets of an operator built with the wrong arity
+    Arity(u8, usize),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Overflow => write!(f, "overflow"),
+            Self::Arity(t, n) => write!(f, "{} can't have {} subpackets", type_name(*t), n),
+        }
+    }
 }
 
+impl std::error::Error for EvalError {}
+
 #[aoc(day16, part1)]
-pub fn solve_part1(input: &[bool]) -> usize {
-    let packet = parse_packet(input, true);
-    recurse_sum(&packet.0)
+pub fn solve_part1(input: &[u8]) -> anyhow::Result<usize> {
+    let packet = decode(input)?;
+    Ok(recurse_sum(&packet))
 }
 #[aoc(day16, part2)]
-pub fn solve_part2(input: &[bool]) -> usize {
-    let packet = parse_packet(input, true);
-    compute_val(&packet.0)
+pub fn solve_part2(input: &[u8]) -> anyhow::Result<usize> {
+    let packet = decode(input)?;
+    Ok(evaluate(&packet)?)
 }
 
 pub fn recurse_sum(p: &Packet) -> usize {
-    let mut local_v = p.version as usize;
-    if let Some(v) = p.subpackets.as_ref() {
-        for sp in v {
-            local_v += recurse_sum(sp);
+    p.version as usize + p.subpackets().iter().map(recurse_sum).sum::<usize>()
+}
+
+pub fn evaluate(p: &Packet) -> Result<usize, EvalError> {
+    apply(p, p.subpackets().iter().map(evaluate).collect())
+}
+
+// Value of p given the values of its subpackets
+fn apply(p: &Packet, values: Result<Vec<usize>, EvalError>) -> Result<usize, EvalError> {
+    use PacketKind::*;
+    if !p.kind.arity_ok() {
+        return Err(EvalError::Arity(p.kind.type_id(), p.subpackets().len()));
+    }
+    let values = values?;
+    Ok(match &p.kind {
+        Literal(v) => *v,
+        Sum(_) => values
+            .iter()
+            .try_fold(0usize, |acc, v| acc.checked_add(*v))
+            .ok_or(EvalError::Overflow)?,
+        Product(_) => values
+            .iter()
+            .try_fold(1usize, |acc, v| acc.checked_mul(*v))
+            .ok_or(EvalError::Overflow)?,
+        Min(_) => *values.iter().min().unwrap(),
+        Max(_) => *values.iter().max().unwrap(),
+        Gt(_) => (values[0] > values[1]) as usize,
+        Lt(_) => (values[0] < values[1]) as usize,
+        Eq(_) => (values[0] == values[1]) as usize,
+    })
+}
+
+pub fn type_name(packet_type: u8) -> &'static str {
+    match packet_type {
+        0 => "sum",
+        1 => "product",
+        2 => "min",
+        3 => "max",
+        4 => "literal",
+        5 => "gt",
+        6 => "lt",
+        7 => "eq",
+        _ => "unknown",
+    }
+}
+
+// One line per packet, indented by depth, showing its header, length field and value
+pub fn pretty_print(p: &Packet) -> String {
+    let mut lines = Vec::new();
+    // The root value is already on the first line
+    let _ = pretty_print_into(p, 0, &mut lines);
+    lines.concat()
+}
+
+// Adds a line for p and each packet inside it, returning p's value so each packet is only
+// evaluated once
+fn pretty_print_into(
+    p: &Packet,
+    depth: usize,
+    lines: &mut Vec<String>,
+) -> Result<usize, EvalError> {
+    let packet_type = p.kind.type_id();
+    let mut out = "  ".repeat(depth);
+    out.push_str(&format!(
+        "v{} {} (type {})",
+        p.version,
+        type_name(packet_type),
+        packet_type
+    ));
+    if let (Some(o), Some(len)) = (p.kind.operands(), p.length_field()) {
+        let unit = if o.length_type { "subpackets" } else { "bits" };
+        out.push_str(&format!(
+            ", length type {}: {} {}",
+            o.length_type as u8, len, unit
+        ));
+    }
+    let line = lines.len();
+    lines.push(out);
+    let values: Vec<_> = p
+        .subpackets()
+        .iter()
+        .map(|sp| pretty_print_into(sp, depth + 1, lines))
+        .collect();
+    let value = apply(p, values.into_iter().collect());
+    match &value {
+        Ok(v) => lines[line].push_str(&format!(" = {}\n", v)),
+        Err(e) => lines[line].push_str(&format!(" = error: {}\n", e)),
+    }
+    value
+}
+
+// The packet as an expression, e.g. `(3 + (4 * 5)) > min(1,2)`. Nested infix operators are
+// bracketed, so evaluating it gives the same result as evaluate.
+pub fn infix(p: &Packet) -> String {
+    infix_nested(p, false)
+}
+
+fn infix_nested(p: &Packet, nested: bool) -> String {
+    use PacketKind::*;
+    let args = p.subpackets();
+    let symbol = match &p.kind {
+        Literal(v) => return v.to_string(),
+        Sum(_) => "+",
+        Product(_) => "*",
+        Gt(_) => ">",
+        Lt(_) => "<",
+        Eq(_) => "==",
+        Min(_) | Max(_) => {
+            let args: Vec<String> = args.iter().map(|sp| infix_nested(sp, false)).collect();
+            return format!("{}({})", type_name(p.kind.type_id()), args.join(","));
         }
+    };
+    if args.len() == 1 {
+        return infix_nested(&args[0], nested);
     }
-    local_v
-}
-
-pub fn compute_val(p: &Packet) -> usize {
-    if let Some(v) = p.value.as_ref() {
-        return *v;
-    }
-    match p.packet_type {
-        0 => p
-            .subpackets
-            .as_ref()
-            .map(|z| z.iter().map(|x| compute_val(&x)).sum())
-            .unwrap_or(0),
-        1 => p
-            .subpackets
-            .as_ref()
-            .map(|z| z.iter().map(|x| compute_val(&x)).fold(1, |acc, v| acc * v))
-            .unwrap_or(0),
-        2 => p
-            .subpackets
-            .as_ref()
-            .map(|z| {
-                z.iter()
-                    .map(|x| compute_val(&x))
-                    .reduce(|a, b| if a < b { a } else { b })
-                    .unwrap_or(0)
-            })
-            .unwrap_or(0),
-        3 => p
-            .subpackets
-            .as_ref()
-            .map(|z| {
-                z.iter()
-                    .map(|x| compute_val(&x))
-                    .reduce(|a, b| if a > b { a } else { b })
-                    .unwrap_or(0)
-            })
-            .unwrap_or(0),
-        5 => p
-            .subpackets
-            .as_ref()
-            .map(|z| {
-                z.iter()
-                    .map(|x| compute_val(&x))
-                    .reduce(|a, b| if a > b { 1 } else { 0 })
-                    .unwrap_or(0)
-            })
-            .unwrap_or(0),
-        6 => p
-            .subpackets
-            .as_ref()
-            .map(|z| {
-                z.iter()
-                    .map(|x| compute_val(&x))
-                    .reduce(|a, b| if a < b { 1 } else { 0 })
-                    .unwrap_or(0)
-            })
-            .unwrap_or(0),
-        7 => p
-            .subpackets
-            .as_ref()
-            .map(|z| {
-                z.iter()
-                    .map(|x| compute_val(&x))
-                    .reduce(|a, b| if a == b { 1 } else { 0 })
-                    .unwrap_or(0)
-            })
-            .unwrap_or(0),
-        _ => panic!("Unexpected type"),
+    let args: Vec<String> = args.iter().map(|sp| infix_nested(sp, true)).collect();
+    let joined = args.join(&format!(" {} ", symbol));
+    if nested {
+        format!("({})", joined)
+    } else {
+        joined
     }
 }
 
-pub fn parse_packet(input: &[bool], root_packet: bool) -> (Packet, usize, &[bool]) {
-    let mut index: usize = 0;
+// Decodes the outermost packet of a transmission, which may only be followed by zero bits
+pub fn decode(bytes: &[u8]) -> Result<Packet, DecodeError> {
+    let mut reader = BitReader::new(bytes);
+    let packet = read_packet(&mut reader)?;
+    reader.finish()?;
+    Ok(packet)
+}
+
+pub fn read_packet(reader: &mut BitReader) -> Result<Packet, DecodeError> {
+    let start = reader.position();
+    let version = reader.read(3)? as u8;
+    let packet_type = reader.read(3)? as u8;
 
-    let version = from_bits(&input[index..index + 3]);
-    index += 3;
-    let packet_type = from_bits(&input[index..index + 3]);
-    index += 3;
     if packet_type == 4 {
-        // Literal value
+        // Literal value, in groups of four bits each with a continuation bit
         let mut value: usize = 0;
         loop {
-            let bits = &input[index..index + 5];
-            index += 5;
-            value = value << 4;
-            value += from_bits(&bits[1..]) as usize;
-            if !bits[0] {
+            let more = reader.read_bit()?;
+            let group = reader.read(4)? as usize;
+            if value.leading_zeros() < 4 {
+                return Err(DecodeError::LiteralTooLarge(start));
+            }
+            value = value << 4 | group;
+            if !more {
                 break;
             }
         }
-        // if index % 4 != 0 {
-        //     index += (4 - (index % 4));
-        // }
-        // Consume padding if in outer packet
-        if root_packet {
-            while index < input.len() {
-                if !input[index] {
-                    index += 1;
-                }
-            }
+        return Ok(Packet::literal(version, value));
+    }
+
+    let length_type = reader.read_bit()?;
+    let mut subpackets = Vec::new();
+    if !length_type {
+        // Total length in bits
+        let length = reader.read(15)? as usize;
+        let end = reader.position() + length;
+        while reader.position() < end {
+            subpackets.push(read_packet(reader)?);
+        }
+        if reader.position() > end {
+            return Err(DecodeError::LengthMismatch(reader.position()));
         }
-        (
-            Packet {
-                version,
-                packet_type,
-                value: Some(value),
-                length_type: None,
-                length_subpackets: None,
-                subpackets: None,
-            },
-            index,
-            &input[index..],
-        )
     } else {
-        let length_type = input[index];
-        index += 1;
-        let mut subpackets = Vec::new();
-        if !length_type {
-            let length_subpackets = from_bits_u16(&input[index..index + 15]);
-            index += 15;
-
-            let mut bits_parsed = 0;
-            let mut packet_input = &input[index..];
-            while bits_parsed < length_subpackets as usize {
-                let p = parse_packet(&packet_input, false);
-                subpackets.push(p.0);
-                bits_parsed += p.1;
-                packet_input = p.2;
+        // Number of subpackets
+        let count = reader.read(11)?;
+        for _ in 0..count {
+            subpackets.push(read_packet(reader)?);
+        }
+    }
+
+    let count = subpackets.len();
+    let operands = Operands {
+        length_type,
+        subpackets,
+    };
+    // Every 3-bit type ID other than the literal's is an operator
+    let kind = PacketKind::operator(packet_type, operands).expect("3-bit operator type ID");
+    if !kind.arity_ok() {
+        return Err(DecodeError::Arity(start, packet_type, count));
+    }
+    Ok(Packet { version, kind })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeError {
+    VersionTooLarge(u8),
+    // Subpacket bits or count that don't fit their length field
+    LengthTooLarge(usize),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::VersionTooLarge(v) => write!(f, "version {} doesn't fit in 3 bits", v),
+            Self::LengthTooLarge(l) => write!(f, "subpacket length {} doesn't fit", l),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+// Appends big-endian bit fields to a byte vector
+#[derive(Debug, Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn write_bit(&mut self, bit: bool) {
+        // Every byte so far is full
+        if self.len == self.bytes.len() * 8 {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 1 << (7 - self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    // Low `bits` bits of value, most significant first
+    pub fn write(&mut self, value: u64, bits: usize) {
+        for i in (0..bits).rev() {
+            self.write_bit(value >> i & 1 == 1);
+        }
+    }
+
+    // Bytes written so far, with the last one padded with zeros
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+fn literal_groups(value: usize) -> usize {
+    (usize::BITS as usize - value.leading_zeros() as usize)
+        .div_ceil(4)
+        .max(1)
+}
+
+pub fn write_packet(writer: &mut BitWriter, p: &Packet) -> Result<(), EncodeError> {
+    if p.version > 7 {
+        return Err(EncodeError::VersionTooLarge(p.version));
+    }
+    writer.write(p.version as u64, 3);
+    writer.write(p.kind.type_id() as u64, 3);
+
+    let o = match &p.kind {
+        PacketKind::Literal(value) => {
+            let groups = literal_groups(*value);
+            for g in (0..groups).rev() {
+                writer.write_bit(g > 0);
+                writer.write((value >> (4 * g)) as u64 & 0xF, 4);
             }
-            index += bits_parsed;
-            // Consume padding if in outer packet
-            if root_packet {
-                while index < input.len() {
-                    if !input[index] {
-                        index += 1;
-                    }
-                }
+            return Ok(());
+        }
+        kind => kind.operands().unwrap(),
+    };
+    writer.write_bit(o.length_type);
+    let length = p.length_field().unwrap();
+    let bits = if o.length_type { 11 } else { 15 };
+    if length >= 1 << bits {
+        return Err(EncodeError::LengthTooLarge(length));
+    }
+    writer.write(length as u64, bits);
+    for sp in o.subpackets.iter() {
+        write_packet(writer, sp)?;
+    }
+    Ok(())
+}
+
+pub fn encode(p: &Packet) -> Result<Vec<u8>, EncodeError> {
+    let mut writer = BitWriter::new();
+    write_packet(&mut writer, p)?;
+    Ok(writer.into_bytes())
+}
+
+// Hex transmission, padded with zeros to a whole number of bytes
+pub fn encode_hex(p: &Packet) -> Result<String, EncodeError> {
+    Ok(encode(p)?.iter().map(|b| format!("{:02X}", b)).collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprError {
+    UnexpectedEnd,
+    // Char position, the char found and what was expected there
+    Unexpected(usize, char, &'static str),
+    UnknownOperator(usize, String),
+    // Position of the operator, its name and how many arguments it was given
+    Arity(usize, String, usize),
+    NumberTooLarge(usize),
+    TrailingInput(usize),
+    // The compiled packet doesn't fit the transmission format
+    Encode(EncodeError),
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "unexpected end of expression"),
+            Self::Unexpected(pos, c, expected) => {
+                write!(f, "expected {} at {}, found {:?}", expected, pos, c)
             }
-            (
-                Packet {
-                    version,
-                    packet_type,
-                    value: None,
-                    length_type: Some(length_type),
-                    length_subpackets: Some(length_subpackets),
-                    subpackets: Some(subpackets),
-                },
-                index,
-                &input[index..],
-            )
-        } else {
-            // num packets
-            let length_subpackets = from_bits_u16(&input[index..index + 11]);
-            index += 11;
-            let mut subpackets = Vec::new();
-            let mut bits_parsed = 0;
-            let mut packet_input = &input[index..];
-            while subpackets.len() < length_subpackets as usize {
-                let p = parse_packet(&packet_input, false);
-                subpackets.push(p.0);
-                bits_parsed += p.1;
-                packet_input = p.2;
+            Self::UnknownOperator(pos, name) => write!(f, "unknown operator {} at {}", name, pos),
+            Self::Arity(pos, name, n) => {
+                write!(f, "{} at {} can't take {} arguments", name, pos, n)
+            }
+            Self::NumberTooLarge(pos) => write!(f, "number at {} is too large", pos),
+            Self::TrailingInput(pos) => write!(f, "unexpected input after expression at {}", pos),
+            Self::Encode(e) => write!(f, "can't encode expression: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+impl From<EncodeError> for ExprError {
+    fn from(e: EncodeError) -> Self {
+        ExprError::Encode(e)
+    }
+}
+
+struct ExprParser {
+    chars: Vec<char>,
+    pos: usize,
+    length_type: bool,
+}
+
+impl ExprParser {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, want: char, expected: &'static str) -> Result<(), ExprError> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos) {
+            None => Err(ExprError::UnexpectedEnd),
+            Some(c) if *c == want => {
+                self.pos += 1;
+                Ok(())
             }
-            index += bits_parsed;
-            // Consume padding if in outer packet
-            if root_packet {
-                while index < input.len() {
-                    if !input[index] {
-                        index += 1;
+            Some(c) => Err(ExprError::Unexpected(self.pos, *c, expected)),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Packet, ExprError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.chars.get(self.pos) {
+            None => Err(ExprError::UnexpectedEnd),
+            Some(c) if c.is_ascii_digit() => {
+                let mut value: usize = 0;
+                while let Some(d) = self.chars.get(self.pos).and_then(|c| c.to_digit(10)) {
+                    value = value
+                        .checked_mul(10)
+                        .and_then(|v| v.checked_add(d as usize))
+                        .ok_or(ExprError::NumberTooLarge(start))?;
+                    self.pos += 1;
+                }
+                Ok(Packet::literal(0, value))
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                while self
+                    .chars
+                    .get(self.pos)
+                    .is_some_and(|c| c.is_ascii_alphabetic())
+                {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                let unknown = || ExprError::UnknownOperator(start, name.clone());
+                let packet_type = (0..8).find(|t| type_name(*t) == name).ok_or_else(unknown)?;
+
+                self.expect('(', "'('")?;
+                let mut args = vec![self.expr()?];
+                loop {
+                    self.skip_whitespace();
+                    if self.chars.get(self.pos) == Some(&')') {
+                        self.pos += 1;
+                        break;
                     }
+                    self.expect(',', "',' or ')'")?;
+                    args.push(self.expr()?);
+                }
+                let count = args.len();
+                let packet =
+                    Packet::operator(0, packet_type, self.length_type, args).ok_or_else(unknown)?;
+                if !packet.kind.arity_ok() {
+                    return Err(ExprError::Arity(start, name, count));
                 }
+                Ok(packet)
             }
-            (
-                Packet {
-                    version,
-                    packet_type,
-                    value: None,
-                    length_type: Some(length_type),
-                    length_subpackets: Some(length_subpackets),
-                    subpackets: Some(subpackets),
-                },
-                index,
-                &input[index..],
-            )
+            Some(c) => Err(ExprError::Unexpected(start, *c, "a number or operator")),
         }
     }
 }
 
+// Compiles e.g. `sum(1, product(2,3), gt(4,5))` into packets with version 0, each operator using
+// the given length type. sum, product, min and max take one or more arguments, gt, lt and eq
+// exactly two.
+pub fn compile(src: &str, length_type: bool) -> Result<Packet, ExprError> {
+    let mut parser = ExprParser {
+        chars: src.chars().collect(),
+        pos: 0,
+        length_type,
+    };
+    let packet = parser.expr()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(ExprError::TrailingInput(parser.pos));
+    }
+    Ok(packet)
+}
+
+pub fn compile_hex(src: &str, length_type: bool) -> Result<String, ExprError> {
+    let packet = compile(src, length_type)?;
+    Ok(encode_hex(&packet)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn bin_str(bytes: &[u8]) -> String {
+        let mut reader = BitReader::new(bytes);
+        (0..bytes.len() * 8)
+            .map(|_| if reader.read_bit().unwrap() { '1' } else { '0' })
+            .collect()
+    }
+    // Packs a string of 0s and 1s into bytes, padding the last with zeros
+    fn from_bin_str(bin: &str) -> Vec<u8> {
+        let bits: Vec<u8> = bin.bytes().map(|c| c - b'0').collect();
+        bits.chunks(8)
+            .map(|chunk| (0..8).fold(0, |b, i| b << 1 | chunk.get(i).unwrap_or(&0)))
+            .collect()
+    }
+    #[test]
+    fn test_read() {
+        let bytes = [0b0011_1000, 0b0000_0000, 0b0110_1111];
+        let mut reader = BitReader::new(&bytes);
+        assert_eq!(reader.read(4), Ok(3));
+        assert_eq!(reader.read(1), Ok(1));
+        assert_eq!(reader.read(15), Ok(0b0110));
+        assert_eq!(reader.position(), 20);
+        assert_eq!(reader.remaining(), 4);
+        assert_eq!(reader.read(5), Err(DecodeError::Truncated(20)));
+        assert_eq!(reader.position(), 20);
+        assert_eq!(reader.finish(), Err(DecodeError::BadPadding(20)));
+        assert_eq!(BitReader::new(&[0, 0]).finish(), Ok(()));
+        assert_eq!(BitReader::new(&[0b0000_0000, 0b0011_0110]).read(15), Ok(27));
+    }
     #[test]
     fn test_to_bits() {
-        assert_eq!(to_bits(15), vec![true, true, true, true]);
+        assert_eq!(&bin_str(&hex_to_bytes("F").unwrap())[..4], "1111");
+        assert_eq!(BitReader::new(&[0xF0]).read(4), Ok(15));
     }
     #[test]
     fn test_from_bits() {
-        assert_eq!(from_bits(&[false, false, true, true]), 3);
+        assert_eq!(BitReader::new(&from_bin_str("0011")).read(4), Ok(3));
     }
     #[test]
     fn test_from_bits_u16() {
-        let bin_str = "000000000011011";
-        assert_eq!(from_bits_u16(&from_bin_str(bin_str)), 27);
+        let bin = "000000000011011";
+        assert_eq!(BitReader::new(&from_bin_str(bin)).read(15), Ok(27));
     }
     #[test]
     fn test_from_bits2() {
-        assert_eq!(from_bits(&[true, false, false]), 4);
+        assert_eq!(BitReader::new(&from_bin_str("100")).read(3), Ok(4));
     }
     #[test]
     fn test_to_bits2() {
         let hex_str = "D2FE28";
-        let bin_str = "110100101111111000101000";
-        assert_eq!(input_generator(hex_str), from_bin_str(bin_str));
+        let bin = "110100101111111000101000";
+        assert_eq!(bin_str(&input_generator(hex_str)), bin);
     }
     #[test]
     fn test_to_bits3() {
         let hex_str = "38006F45291200";
-        let bin_str = "00111000000000000110111101000101001010010001001000000000";
-        assert_eq!(input_generator(hex_str), from_bin_str(bin_str));
+        let bin = "00111000000000000110111101000101001010010001001000000000";
+        assert_eq!(bin_str(&input_generator(hex_str)), bin);
+        assert_eq!(hex_to_bytes("F0A"), Ok(vec![0xF0, 0xA0]));
+        assert_eq!(hex_to_bytes("F0G"), Err(DecodeError::InvalidHex(2, 'G')));
     }
     #[test]
     fn test_one_packet() {
         let hex_str = "D2FE28";
-        let empty: &[bool] = &[];
         assert_eq!(
-            parse_packet(&input_generator(hex_str), true),
-            (
-                Packet {
-                    version: 6,
-                    packet_type: 4,
-                    value: Some(2021),
-                    length_type: None,
-                    length_subpackets: None,
-                    subpackets: None
-                },
-                24,
-                empty
-            )
+            decode(&input_generator(hex_str)),
+            Ok(Packet {
+                version: 6,
+                kind: PacketKind::Literal(2021),
+            })
         );
     }
     #[test]
     fn test_simple_packets() {
         let hex_str = "38006F45291200";
-        let empty: &[bool] = &[];
+        let packet = decode(&input_generator(hex_str)).unwrap();
         assert_eq!(
-            parse_packet(&input_generator(hex_str), true),
-            (
-                Packet {
-                    version: 1,
-                    packet_type: 6,
-                    value: None,
-                    length_type: Some(false),
-                    length_subpackets: Some(27),
-                    subpackets: Some(vec![
-                        Packet {
-                            version: 6,
-                            packet_type: 4,
-                            value: Some(10),
-                            length_type: None,
-                            length_subpackets: None,
-                            subpackets: None
-                        },
-                        Packet {
-                            version: 2,
-                            packet_type: 4,
-                            value: Some(20),
-                            length_type: None,
-                            length_subpackets: None,
-                            subpackets: None
-                        },
-                    ])
-                },
-                56,
-                empty
-            )
+            packet,
+            Packet {
+                version: 1,
+                kind: PacketKind::Lt(Operands {
+                    length_type: false,
+                    subpackets: vec![Packet::literal(6, 10), Packet::literal(2, 20)],
+                }),
+            }
         );
+        assert_eq!(packet.length_field(), Some(27));
+        assert_eq!(packet.bit_len(), 49);
     }
     #[test]
     fn test_more_packets() {
         let hex_str = "EE00D40C823060";
-        let empty: &[bool] = &[];
+        let packet = decode(&input_generator(hex_str)).unwrap();
         assert_eq!(
-            parse_packet(&input_generator(hex_str), true),
-            (
-                Packet {
-                    version: 7,
-                    packet_type: 3,
-                    value: None,
-                    length_type: Some(true),
-                    length_subpackets: Some(3),
-                    subpackets: Some(vec![
-                        Packet {
-                            version: 2,
-                            packet_type: 4,
-                            value: Some(1),
-                            length_type: None,
-                            length_subpackets: None,
-                            subpackets: None
-                        },
-                        Packet {
-                            version: 4,
-                            packet_type: 4,
-                            value: Some(2),
-                            length_type: None,
-                            length_subpackets: None,
-                            subpackets: None
-                        },
-                        Packet {
-                            version: 1,
-                            packet_type: 4,
-                            value: Some(3),
-                            length_type: None,
-                            length_subpackets: None,
-                            subpackets: None
-                        },
-                    ])
-                },
-                56,
-                empty
+            packet,
+            Packet::operator(
+                7,
+                3,
+                true,
+                vec![
+                    Packet::literal(2, 1),
+                    Packet::literal(4, 2),
+                    Packet::literal(1, 3),
+                ]
             )
+            .unwrap()
         );
+        assert_eq!(packet.length_field(), Some(3));
+        assert_eq!(packet.kind().type_id(), 3);
     }
     #[test]
     fn test_part_1_1() {
         let hex_str = "8A004A801A8002F478";
-        assert_eq!(solve_part1(&input_generator(hex_str)), 16);
+        assert_eq!(solve_part1(&input_generator(hex_str)).unwrap(), 16);
     }
     #[test]
     fn test_part_2_1() {
         let hex_str = "C200B40A82";
-        assert_eq!(solve_part2(&input_generator(hex_str)), 3);
+        assert_eq!(solve_part2(&input_generator(hex_str)).unwrap(), 3);
     }
     #[test]
     fn test_part_2_2() {
         let hex_str = "04005AC33890";
-        assert_eq!(solve_part2(&input_generator(hex_str)), 54);
+        assert_eq!(solve_part2(&input_generator(hex_str)).unwrap(), 54);
+    }
+    #[test]
+    fn test_decode_errors() {
+        // Literal cut off in its third group
+        assert_eq!(
+            decode(&input_generator("D2F")),
+            Err(DecodeError::Truncated(16))
+        );
+        // Operator with 27 bits of subpackets and a stray bit set in the padding
+        assert_eq!(
+            decode(&input_generator("38006F45291201")),
+            Err(DecodeError::BadPadding(55))
+        );
+        // Operator claiming 2 bits of subpackets
+        assert_eq!(
+            decode(&input_generator("38000B450")),
+            Err(DecodeError::LengthMismatch(33))
+        );
+        // Literal with seventeen groups of 0xF
+        assert_eq!(
+            decode(&input_generator("D3FFFFFFFFFFFFFFFFFFFDE")),
+            Err(DecodeError::LiteralTooLarge(0))
+        );
+        assert_eq!(
+            DecodeError::Truncated(12).to_string(),
+            "transmission ends early at bit 12"
+        );
+    }
+    #[test]
+    fn test_encode() {
+        for hex_str in ["D2FE28", "38006F45291200", "EE00D40C823060"] {
+            let packet = decode(&input_generator(hex_str)).unwrap();
+            assert_eq!(encode_hex(&packet).unwrap(), hex_str);
+        }
+        for hex_str in [
+            "8A004A801A8002F478",
+            "620080001611562C8802118E34",
+            "C0015000016115A2E0802F182340",
+            "A0016C880162017C3686B18A3D4780",
+            "C200B40A82",
+            "04005AC33890",
+            "9C0141080250320F1802104A08",
+        ] {
+            let packet = decode(&input_generator(hex_str)).unwrap();
+            let encoded = encode_hex(&packet).unwrap();
+            assert!(hex_str.starts_with(&encoded), "{} {}", hex_str, encoded);
+            assert_eq!(decode(&hex_to_bytes(&encoded).unwrap()), Ok(packet));
+        }
+    }
+    #[test]
+    fn test_encode_built() {
+        // 1 + 2 == 3, with both length types
+        let packet = Packet::operator(
+            1,
+            7,
+            false,
+            vec![
+                Packet::operator(
+                    2,
+                    0,
+                    true,
+                    vec![Packet::literal(3, 1), Packet::literal(4, 2)],
+                )
+                .unwrap(),
+                Packet::literal(5, 3),
+            ],
+        )
+        .unwrap();
+        assert_eq!(packet.bit_len(), 22 + 18 + 11 + 11 + 11);
+        let bytes = encode(&packet).unwrap();
+        assert_eq!(bytes.len(), 10);
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded, packet);
+        assert_eq!(evaluate(&decoded), Ok(1));
+        assert_eq!(recurse_sum(&decoded), 15);
+
+        let big = Packet::literal(0, usize::MAX);
+        assert_eq!(decode(&encode(&big).unwrap()), Ok(big));
+        assert_eq!(
+            encode(&Packet::literal(8, 1)),
+            Err(EncodeError::VersionTooLarge(8))
+        );
+        let many = Packet::operator(0, 0, true, vec![Packet::literal(0, 0); 2048]).unwrap();
+        assert_eq!(encode(&many), Err(EncodeError::LengthTooLarge(2048)));
+    }
+    #[test]
+    fn test_compile() {
+        for (src, expected) in [
+            ("sum(1, product(2,3), min(4,5))", 11),
+            ("42", 42),
+            ("max(7)", 7),
+            ("gt(product(3, 4), sum(5, 6))", 1),
+            ("lt(product(3, 4), sum(5, 6))", 0),
+            ("eq(sum(1, 3), product(2, 2))", 1),
+            (" product ( 1000000 , 1000000 ) ", 1000000000000),
+        ] {
+            for length_type in [false, true] {
+                let packet = compile(src, length_type).unwrap();
+                let hex_str = compile_hex(src, length_type).unwrap();
+                assert_eq!(decode(&input_generator(&hex_str)), Ok(packet));
+                assert_eq!(
+                    solve_part2(&input_generator(&hex_str)).unwrap(),
+                    expected,
+                    "{}",
+                    src
+                );
+            }
+        }
+        assert_eq!(
+            compile_hex("sum(1,2)", true),
+            Ok(String::from("0200840882"))
+        );
+    }
+    #[test]
+    fn test_compile_errors() {
+        use ExprError::*;
+        assert_eq!(compile("sum(1,", true), Err(UnexpectedEnd));
+        assert_eq!(
+            compile("sum(1;2)", true),
+            Err(Unexpected(5, ';', "',' or ')'"))
+        );
+        assert_eq!(
+            compile("sum()", true),
+            Err(Unexpected(4, ')', "a number or operator"))
+        );
+        assert_eq!(
+            compile("avg(1,2)", true),
+            Err(UnknownOperator(0, String::from("avg")))
+        );
+        assert_eq!(
+            compile("sum(literal(1))", true),
+            Err(UnknownOperator(4, String::from("literal")))
+        );
+        assert_eq!(
+            compile("sum(1, gt(1,2,3))", true),
+            Err(Arity(7, String::from("gt"), 3))
+        );
+        assert_eq!(compile("sum 1", true), Err(Unexpected(4, '1', "'('")));
+        assert_eq!(compile("1 2", true), Err(TrailingInput(2)));
+        assert_eq!(
+            compile("99999999999999999999", true),
+            Err(NumberTooLarge(0))
+        );
+        assert_eq!(
+            compile("gt(1)", true).unwrap_err().to_string(),
+            "gt at 0 can't take 1 arguments"
+        );
+        // The subpacket count field is 11 bits
+        let src = format!("sum({})", vec!["1"; 2048].join(","));
+        assert!(compile(&src, true).is_ok());
+        assert_eq!(
+            compile_hex(&src, true),
+            Err(Encode(EncodeError::LengthTooLarge(2048)))
+        );
+        assert!(compile_hex(&src, false).is_ok());
+    }
+    #[test]
+    fn test_pretty_print() {
+        let packet = decode(&input_generator("9C0141080250320F1802104A08")).unwrap();
+        assert_eq!(
+            pretty_print(&packet),
+            "v4 eq (type 7), length type 0: 80 bits = 1
+  v2 sum (type 0), length type 1: 2 subpackets = 4
+    v2 literal (type 4) = 1
+    v4 literal (type 4) = 3
+  v6 product (type 1), length type 1: 2 subpackets = 4
+    v0 literal (type 4) = 2
+    v2 literal (type 4) = 2
+"
+        );
+        assert_eq!(infix(&packet), "(1 + 3) == (2 * 2)");
+
+        // A chain of 200 sums prints every level with the same value
+        let src = format!("{}1{}", "sum(".repeat(200), ")".repeat(200));
+        let printed = pretty_print(&compile(&src, true).unwrap());
+        assert_eq!(printed.lines().count(), 201);
+        assert!(printed.lines().all(|l| l.ends_with(" = 1")));
+    }
+    #[test]
+    fn test_infix() {
+        for (src, expected) in [
+            (
+                "gt(sum(3, product(4, 5)), min(1, 2))",
+                "(3 + (4 * 5)) > min(1,2)",
+            ),
+            ("sum(1, 2, max(3, sum(4)))", "1 + 2 + max(3,4)"),
+            ("product(sum(7))", "7"),
+            ("lt(1, eq(2, 2))", "1 < (2 == 2)"),
+        ] {
+            assert_eq!(infix(&compile(src, true).unwrap()), expected);
+        }
+    }
+    #[test]
+    fn test_arity() {
+        assert_eq!(Packet::operator(0, 4, true, vec![]), None);
+        // gt with only one subpacket
+        let gt = Packet::operator(0, 5, true, vec![Packet::literal(0, 1)]).unwrap();
+        let hex_str = encode_hex(&gt).unwrap();
+        assert_eq!(
+            decode(&input_generator(&hex_str)),
+            Err(DecodeError::Arity(0, 5, 1))
+        );
+        assert_eq!(evaluate(&gt), Err(EvalError::Arity(5, 1)));
+        let empty_sum = Packet::operator(0, 0, false, vec![]).unwrap();
+        assert_eq!(
+            decode(&encode(&empty_sum).unwrap()),
+            Err(DecodeError::Arity(0, 0, 0))
+        );
+        assert_eq!(
+            DecodeError::Arity(0, 5, 1).to_string(),
+            "gt at bit 0 can't have 1 subpackets"
+        );
+    }
+    #[test]
+    fn test_evaluate() {
+        let packet = compile("product(4294967296, 4294967296)", true).unwrap();
+        assert_eq!(evaluate(&packet), Err(EvalError::Overflow));
+        assert!(pretty_print(&packet)
+            .starts_with("v0 product (type 1), length type 1: 2 subpackets = error: overflow\n"));
+        let packet = compile("sum(18446744073709551614, lt(1, 2))", false).unwrap();
+        assert_eq!(evaluate(&packet), Ok(usize::MAX));
+        assert_eq!(
+            solve_part2(&input_generator("9C0141080250320F1802104A08")).unwrap(),
+            1
+        );
+        // Both parts report bad transmissions and part 2 reports bad arithmetic
+        let truncated = input_generator("C200B4");
+        assert_eq!(
+            solve_part1(&truncated).unwrap_err().downcast_ref(),
+            Some(&DecodeError::Truncated(24))
+        );
+        assert!(solve_part2(&truncated).is_err());
+        let overflow =
+            input_generator(&compile_hex("product(4294967296, 4294967296)", true).unwrap());
+        assert_eq!(
+            solve_part2(&overflow).unwrap_err().downcast_ref(),
+            Some(&EvalError::Overflow)
+        );
     }
 }
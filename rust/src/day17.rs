@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 
 use aoc_runner_derive::{aoc, aoc_generator};
 use lazy_static::lazy_static;
@@ -21,82 +21,200 @@ pub fn input_generator(input: &str) -> ((i32, i32), (i32, i32)) {
     )
 }
 
-#[aoc(day17, part1)]
-pub fn solve_part1(input: &((i32, i32), (i32, i32))) -> i32 {
-    let mut max_y = -999999;
-    for xvel in 0..=1000 {
-        for yvel in -1000..=1000 {
-            let mut vx = xvel;
-            let mut vy = yvel;
-            let mut xpos = 0;
-            let mut ypos = 0;
-            let mut local_max_y = -9999999;
-            loop {
-                if ypos > local_max_y {
-                    local_max_y = ypos;
-                }
-                if xpos >= input.0 .0
-                    && xpos <= input.0 .1
-                    && ypos >= input.1 .0
-                    && ypos <= input.1 .1
-                {
-                    if local_max_y > max_y {
-                        max_y = local_max_y;
-                        // println!("{}, {}", xvel, yvel);
-                    }
-                }
+fn tri(n: i32) -> i32 {
+    n * (n + 1) / 2
+}
 
-                if xpos > input.0 .1 || (ypos < input.1 .0 && vy < 0) {
-                    break;
-                }
+// Smallest v >= 0 with tri(v) >= d
+fn tri_root(d: i32) -> i32 {
+    let mut v = ((((8 * d.max(0) + 1) as f64).sqrt() - 1.0) / 2.0).floor() as i32;
+    while tri(v) < d {
+        v += 1;
+    }
+    v
+}
 
-                xpos += vx;
-                ypos += vy;
-                if vx > 0 {
-                    vx -= 1;
-                } else if vx < 0 {
-                    vx += 1;
-                }
-                vy -= 1;
-            }
+// First and last step with x in range, where None for the last step means x stops inside the
+// range. x only moves one way, so the steps in range are contiguous.
+fn x_steps(vx: i32, range: (i32, i32)) -> Option<(i32, Option<i32>)> {
+    let (mut x, mut v) = (0, vx);
+    let mut steps: Option<(i32, i32)> = None;
+    for n in 0.. {
+        let inside = x >= range.0 && x <= range.1;
+        if inside {
+            steps = Some((steps.map_or(n, |s| s.0), n));
+        }
+        if v == 0 {
+            return match (steps, inside) {
+                (Some((first, _)), true) => Some((first, None)),
+                _ => steps.map(|(first, last)| (first, Some(last))),
+            };
         }
+        x += v;
+        v -= v.signum();
     }
-    max_y
+    unreachable!()
 }
-#[aoc(day17, part2)]
-pub fn solve_part2(input: &((i32, i32), (i32, i32))) -> usize {
-    let mut velset = HashSet::new();
-    for xvel in 0..=1000 {
-        for yvel in -1000..=1000 {
-            let mut vx = xvel;
-            let mut vy = yvel;
-            let mut xpos = 0;
-            let mut ypos = 0;
-            loop {
-                if xpos >= input.0 .0
-                    && xpos <= input.0 .1
-                    && ypos >= input.1 .0
-                    && ypos <= input.1 .1
-                {
-                    velset.insert((xvel, yvel));
-                }
 
-                if xpos > input.0 .1 || (ypos < input.1 .0 && vy < 0) {
-                    break;
-                }
+// All steps with y in range. Going up and coming down can both pass through it.
+fn y_steps(vy: i32, range: (i32, i32)) -> Vec<i32> {
+    let (mut y, mut v) = (0, vy);
+    let mut steps = Vec::new();
+    for n in 0.. {
+        if y < range.0 && v < 0 {
+            break;
+        }
+        if y <= range.1 && y >= range.0 {
+            steps.push(n);
+        }
+        y += v;
+        v -= 1;
+    }
+    steps
+}
 
-                xpos += vx;
-                ypos += vy;
-                if vx > 0 {
-                    vx -= 1;
-                } else if vx < 0 {
-                    vx += 1;
-                }
-                vy -= 1;
+// Every starting velocity which puts the probe inside the target at some step, sorted, for a
+// target anywhere relative to the origin. None if there are infinitely many, which happens when
+// the y range contains 0 and x can come to rest inside the target: then any high enough throw
+// comes back down through y=0 while x is in range.
+pub fn hitting_velocities(target: &((i32, i32), (i32, i32))) -> Option<Vec<(i32, i32)>> {
+    let ((x0, x1), (y0, y1)) = *target;
+
+    // x only changes by vx on the first step and never turns back, so it can't pass beyond the
+    // far edge. Reaching the near edge needs a triangular number of at least its distance.
+    let vx_min = if x0 > 0 { tri_root(x0) } else { x0 };
+    let vx_max = if x1 < 0 { -tri_root(-x1) } else { x1 };
+    let xs: Vec<(i32, (i32, Option<i32>))> = (vx_min..=vx_max)
+        .filter_map(|vx| x_steps(vx, (x0, x1)).map(|s| (vx, s)))
+        .collect();
+
+    let (vy_min, vy_max) = if y1 < 0 {
+        // Going up it comes back to 0 with speed vy + 1, which mustn't skip past y0
+        (y0, -y0 - 1)
+    } else if y0 > 0 {
+        // Needs to peak at y0, and every height reached is reached on the way up
+        (tri_root(y0), y1)
+    } else {
+        if xs.iter().any(|(_, (_, last))| last.is_none()) {
+            return None;
+        }
+        // Hitting at step n >= 1 needs n * vy - tri(n - 1) <= y1, so vy <= y1 + (n - 1) / 2
+        let n = xs
+            .iter()
+            .filter_map(|(_, (_, last))| *last)
+            .max()
+            .unwrap_or(0);
+        (y0, y1 + n / 2)
+    };
+
+    let mut hits = Vec::new();
+    for vy in vy_min..=vy_max {
+        let ys = y_steps(vy, (y0, y1));
+        for (vx, (first, last)) in xs.iter() {
+            if ys
+                .iter()
+                .any(|n| n >= first && !matches!(last, Some(last) if n > last))
+            {
+                hits.push((*vx, vy));
             }
         }
     }
-    velset.len()
+    hits.sort();
+    Some(hits)
+}
+
+// Positions of the probe after each step, starting at the origin
+pub fn trajectory(vx: i32, vy: i32) -> impl Iterator<Item = (i32, i32)> {
+    std::iter::successors(Some((0, 0, vx, vy)), |(x, y, vx, vy)| {
+        Some((x + vx, y + vy, vx - vx.signum(), vy - 1))
+    })
+    .map(|(x, y, _, _)| (x, y))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    // Step at which the probe is first inside the target
+    Hit(usize),
+    // Went past the far side of the target from the origin
+    Overshoot,
+    // Fell short or stepped over the target
+    Miss,
+}
+
+// Trajectory up to the first hit, or until it has fallen below the target for good
+fn path(target: &((i32, i32), (i32, i32)), vx: i32, vy: i32) -> (Vec<(i32, i32)>, Outcome) {
+    let ((x0, x1), (y0, y1)) = *target;
+    let mut points = Vec::new();
+    let mut overshot = false;
+    for (n, (x, y)) in trajectory(vx, vy).enumerate() {
+        // Falling (vy - n < 0) below the target, it can't come back up
+        if y < y0 && vy < n as i32 {
+            break;
+        }
+        points.push((x, y));
+        if x >= x0 && x <= x1 && y >= y0 && y <= y1 {
+            return (points, Outcome::Hit(n));
+        }
+        overshot |= (x > x1 && x1 >= 0) || (x < x0 && x0 <= 0);
+    }
+    let outcome = if overshot {
+        Outcome::Overshoot
+    } else {
+        Outcome::Miss
+    };
+    (points, outcome)
+}
+
+pub fn classify(target: &((i32, i32), (i32, i32)), vx: i32, vy: i32) -> Outcome {
+    path(target, vx, vy).1
+}
+
+// Diagram in the puzzle's style: S for the start, T for the target and # for each position
+// the probe reaches, up to the hit or until it's fallen past the target
+pub fn plot(target: &((i32, i32), (i32, i32)), vx: i32, vy: i32) -> String {
+    let ((x0, x1), (y0, y1)) = *target;
+    let (points, _) = path(target, vx, vy);
+    let xs = points.iter().map(|p| p.0).chain([x0, x1]);
+    let ys = points.iter().map(|p| p.1).chain([y0, y1]);
+    let (min_x, max_x) = (xs.clone().min().unwrap(), xs.max().unwrap());
+    let (min_y, max_y) = (ys.clone().min().unwrap(), ys.max().unwrap());
+    let points: HashSet<(i32, i32)> = points.into_iter().collect();
+
+    (min_y..=max_y)
+        .rev()
+        .map(|y| {
+            (min_x..=max_x)
+                .map(|x| {
+                    if (x, y) == (0, 0) {
+                        'S'
+                    } else if points.contains(&(x, y)) {
+                        '#'
+                    } else if x >= x0 && x <= x1 && y >= y0 && y <= y1 {
+                        'T'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[aoc(day17, part1)]
+pub fn solve_part1(input: &((i32, i32), (i32, i32))) -> i32 {
+    hitting_velocities(input)
+        .expect("infinitely many velocities hit the target")
+        .iter()
+        .map(|(_, vy)| tri(*vy.max(&0)))
+        .max()
+        .expect("no velocity hits the target")
+}
+#[aoc(day17, part2)]
+pub fn solve_part2(input: &((i32, i32), (i32, i32))) -> usize {
+    hitting_velocities(input)
+        .expect("infinitely many velocities hit the target")
+        .len()
 }
 #[cfg(test)]
 mod tests {
@@ -120,4 +238,116 @@ mod tests {
         let parsed = input_generator(inp);
         assert_eq!(solve_part2(&parsed), 112);
     }
+    // Straight simulation over a fixed box of velocities
+    fn brute_force(target: &((i32, i32), (i32, i32))) -> Vec<(i32, i32)> {
+        let ((x0, x1), (y0, y1)) = *target;
+        let mut hits = Vec::new();
+        for vx in -100..=100 {
+            for vy in -100..=100 {
+                let (mut x, mut y, mut v, mut w) = (0, 0, vx, vy);
+                for _ in 0..300 {
+                    if x >= x0 && x <= x1 && y >= y0 && y <= y1 {
+                        hits.push((vx, vy));
+                        break;
+                    }
+                    x += v;
+                    y += w;
+                    v -= i32::signum(v);
+                    w -= 1;
+                }
+            }
+        }
+        hits
+    }
+    #[test]
+    fn test_hitting_velocities() {
+        let hits = hitting_velocities(&((20, 30), (-10, -5))).unwrap();
+        assert_eq!(hits.len(), 112);
+        assert!(hits.contains(&(6, 9)) && hits.contains(&(30, -10)));
+
+        for target in [
+            ((20, 30), (-10, -5)),
+            ((-30, -20), (-10, -5)),
+            ((20, 30), (5, 10)),
+            ((-30, -20), (5, 10)),
+            ((-5, 5), (-10, -5)),
+            ((-3, 4), (3, 7)),
+            ((8, 9), (-5, 5)),
+            ((-12, -11), (-1, 0)),
+        ] {
+            assert_eq!(
+                hitting_velocities(&target),
+                Some(brute_force(&target)),
+                "{:?}",
+                target
+            );
+        }
+        // x can stop at 21 while high throws come back down through 0
+        assert_eq!(hitting_velocities(&((20, 30), (-5, 5))), None);
+        assert_eq!(hitting_velocities(&((-1, 1), (-1, 1))), None);
+    }
+    #[test]
+    fn test_trajectory() {
+        let points: Vec<(i32, i32)> = trajectory(7, 2).take(8).collect();
+        assert_eq!(
+            points,
+            vec![
+                (0, 0),
+                (7, 2),
+                (13, 3),
+                (18, 3),
+                (22, 2),
+                (25, 0),
+                (27, -3),
+                (28, -7)
+            ]
+        );
+        assert_eq!(trajectory(-2, 0).nth(4), Some((-3, -6)));
+
+        let target = ((20, 30), (-10, -5));
+        assert_eq!(classify(&target, 7, 2), Outcome::Hit(7));
+        assert_eq!(classify(&target, 6, 3), Outcome::Hit(9));
+        assert_eq!(classify(&target, 9, 0), Outcome::Hit(4));
+        assert_eq!(classify(&target, 17, -4), Outcome::Overshoot);
+        assert_eq!(classify(&target, 6, 10), Outcome::Miss);
+        assert_eq!(classify(&target, -7, 2), Outcome::Miss);
+        for (vx, vy) in hitting_velocities(&target).unwrap() {
+            assert!(matches!(classify(&target, vx, vy), Outcome::Hit(_)));
+        }
+    }
+    #[test]
+    fn test_plot() {
+        let target = ((20, 30), (-10, -5));
+        assert_eq!(
+            plot(&target, 7, 2),
+            ".............#....#............
+.......#..............#........
+...............................
+S........................#.....
+...............................
+...............................
+...........................#...
+...............................
+....................TTTTTTTTTTT
+....................TTTTTTTTTTT
+....................TTTTTTTT#TT
+....................TTTTTTTTTTT
+....................TTTTTTTTTTT
+....................TTTTTTTTTTT"
+        );
+        assert_eq!(
+            plot(&target, 17, -4),
+            "S.................................
+..................................
+..................................
+..................................
+.................#................
+....................TTTTTTTTTTT...
+....................TTTTTTTTTTT...
+....................TTTTTTTTTTT...
+....................TTTTTTTTTTT...
+....................TTTTTTTTTTT..#
+....................TTTTTTTTTTT..."
+        );
+    }
 }
@@ -1,4 +1,8 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::Add;
+use std::str::FromStr;
 
 use aoc_runner_derive::{aoc, aoc_generator};
 use lazy_static::lazy_static;
@@ -15,216 +19,430 @@ impl List {
         3 * self.left.magnitude() + 2 * self.right.magnitude()
     }
 
-    pub fn add(self, other: Self) -> Self {
+    // The pair [self, other] without reducing it
+    pub fn pair(self, other: Self) -> Self {
         Self {
             left: EitherList::List(Box::new(self)),
             right: EitherList::List(Box::new(other)),
         }
     }
-    pub fn explode(mut self) -> Self {
-        recurse_down(&mut self, 0, false).0
+
+    #[deprecated(note = "doesn't reduce: use List::pair, or + for snailfish addition")]
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(self, other: Self) -> Self {
+        self.pair(other)
+    }
+    pub fn explode(self) -> Self {
+        let mut flat = FlatList::from(&self);
+        flat.explode();
+        List::from(&flat)
     }
 }
 
+// Explodes the leftmost pair inside l when l is itself nested inside `depth` pairs, unless an
+// earlier step has already acted. Returns the new list, the halves of the exploded pair which
+// had no regular number to land on inside l, and whether anything exploded.
 pub fn recurse_down(
     l: &mut List,
     depth: i32,
-    mut found: bool,
+    found: bool,
 ) -> (List, Option<i32>, Option<i32>, bool) {
-    let mut new_list: List = l.clone();
     if found {
-        return (new_list, None, None, true);
-    }
-    if depth >= 3 {
-        // dbg!(l.clone());
-        match (l.left.clone(), l.right.clone()) {
-            (EitherList::Value(_), EitherList::Value(_)) => return (new_list, None, None, found),
-            (EitherList::List(l2), EitherList::Value(rv)) => {
-                // dbg!((l2.clone(), rv));
-                found = true;
-                new_list.right = EitherList::Value(rv + l2.right.magnitude());
-                new_list.left = EitherList::Value(0);
-                return (new_list, Some(l2.left.magnitude()), None, true);
-            }
-            (EitherList::Value(lv), EitherList::List(r2)) => {
-                // dbg!((r2.clone(), lv));
-                found = true;
-                new_list.left = EitherList::Value(lv + r2.left.magnitude());
-                new_list.right = EitherList::Value(0);
-                return (new_list, None, Some(r2.right.magnitude()), true);
-            }
-            (EitherList::List(l2), EitherList::List(r2)) => {
-                // Adjacent explosions
-                // Do left first and break for recall - then right will be only one next call
-                found = true;
-                new_list.right = EitherList::List(Box::new(add_left(&r2, l2.right.magnitude())));
-                new_list.left = EitherList::Value(0);
-                return (new_list, Some(l2.left.magnitude()), None, true);
-            }
+        return (l.clone(), None, None, true);
+    }
+    let mut flat = FlatList::from(&*l);
+    for cell in flat.0.iter_mut() {
+        cell.0 += depth as u32;
+    }
+    // l itself can't explode, only pairs inside it
+    let i = match flat.explode_index() {
+        Some(i) if flat.0.len() > 2 => i,
+        _ => return (l.clone(), None, None, false),
+    };
+    let carry_left = (i == 0).then_some(flat.0[i].1);
+    let carry_right = (i + 2 == flat.0.len()).then_some(flat.0[i + 1].1);
+    flat.explode();
+    for cell in flat.0.iter_mut() {
+        cell.0 -= depth as u32;
+    }
+    (List::from(&flat), carry_left, carry_right, true)
+}
+
+// Splits the leftmost large regular number unless an earlier step has already acted
+pub fn split(l: &List, found: bool) -> (List, bool) {
+    if found {
+        return (l.clone(), true);
+    }
+    let mut flat = FlatList::from(l);
+    let found = flat.split();
+    (List::from(&flat), found)
+}
+
+pub fn reduce(l: &List) -> List {
+    let mut flat = FlatList::from(l);
+    flat.reduce();
+    List::from(&flat)
+}
+
+// Adds v to the leftmost regular number
+pub fn add_left(l: &List, v: i32) -> List {
+    let mut flat = FlatList::from(l);
+    flat.0[0].1 += v;
+    List::from(&flat)
+}
+
+// Adds v to the rightmost regular number
+pub fn add_right(l: &List, v: i32) -> List {
+    let mut flat = FlatList::from(l);
+    if let Some(last) = flat.0.last_mut() {
+        last.1 += v;
+    }
+    List::from(&flat)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EitherList {
+    List(Box<List>),
+    Value(i32),
+}
+
+impl EitherList {
+    pub fn magnitude(&self) -> i32 {
+        match self {
+            Self::List(l) => l.magnitude(),
+            Self::Value(v) => *v,
         }
-    } else {
-        let mut new_left = match l.left.clone() {
-            EitherList::List(mut l2) => {
-                // dbg!((l2.clone(), depth + 1));
-                let temp = recurse_down(&mut l2, depth + 1, found);
-                (EitherList::List(Box::new(temp.0)), temp.1, temp.2, temp.3)
-            }
-            EitherList::Value(_) => (l.left.clone(), None, None, false),
+    }
+}
+
+// Regular numbers from left to right, each with the number of pairs it's nested inside.
+// Both halves of a pair of regular numbers have the same depth and are adjacent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatList(Vec<(u32, i32)>);
+
+impl FlatList {
+    pub fn add(&mut self, other: &FlatList) {
+        self.0.extend(other.0.iter());
+        for cell in self.0.iter_mut() {
+            cell.0 += 1;
+        }
+    }
+
+    // Explodes the leftmost pair of regular numbers nested inside four or more pairs, returning
+    // whether there was one
+    pub fn explode(&mut self) -> bool {
+        let i = match self.explode_index() {
+            Some(i) => i,
+            None => return false,
         };
-        found = found || new_left.3;
-        let mut new_right = match l.right.clone() {
-            EitherList::List(mut l2) => {
-                // dbg!((l2.clone(), depth + 1));
-                let temp = recurse_down(&mut l2, depth + 1, found);
-                (EitherList::List(Box::new(temp.0)), temp.1, temp.2, temp.3)
-            }
-            EitherList::Value(_) => (l.right.clone(), None, None, false),
+        let (depth, left) = self.0[i];
+        let (_, right) = self.0.remove(i + 1);
+        if i > 0 {
+            self.0[i - 1].1 += left;
+        }
+        if let Some(next) = self.0.get_mut(i + 1) {
+            next.1 += right;
+        }
+        self.0[i] = (depth - 1, 0);
+        true
+    }
+
+    // Splits the leftmost regular number of 10 or more, returning whether there was one
+    pub fn split(&mut self) -> bool {
+        let i = match self.split_index() {
+            Some(i) => i,
+            None => return false,
         };
+        let (depth, v) = self.0[i];
+        self.0[i] = (depth + 1, v / 2);
+        self.0.insert(i + 1, (depth + 1, v - v / 2));
+        true
+    }
+
+    // Index of the left half of the pair explode would act on. A deep number followed by a
+    // deeper one is the left half of a pair whose right half is still a pair, so skip it.
+    fn explode_index(&self) -> Option<usize> {
+        self.0
+            .windows(2)
+            .position(|w| w[0].0 > 4 && w[1].0 == w[0].0)
+    }
 
-        new_list.right = new_right.0.clone();
-        new_list.left = new_left.0.clone();
+    fn split_index(&self) -> Option<usize> {
+        self.0.iter().position(|(_, v)| *v >= 10)
+    }
 
-        if let Some(leftadd) = new_left.2 {
-            new_list.right = match new_right.0 {
-                EitherList::Value(v) => EitherList::Value(v + leftadd),
-                EitherList::List(l2) => EitherList::List(Box::new(add_left(&l2, leftadd))),
+    // Path from the root to the regular number at index i
+    pub fn leaf_path(&self, i: usize) -> Vec<Side> {
+        // Each leaf's path is the previous one with its trailing rights dropped, the last left
+        // turned right, then extended left down to the leaf's depth
+        let mut path: Vec<Side> = Vec::new();
+        for (j, (depth, _)) in self.0[..=i].iter().enumerate() {
+            if j > 0 {
+                while path.last() == Some(&Side::R) {
+                    path.pop();
+                }
+                path.pop();
+                path.push(Side::R);
             }
+            path.resize(*depth as usize, Side::L);
         }
-        if let Some(rightadd) = new_right.1 {
-            new_list.left = match new_left.0 {
-                EitherList::Value(v) => EitherList::Value(v + rightadd),
-                EitherList::List(l2) => EitherList::List(Box::new(add_right(&l2, rightadd))),
+        path
+    }
+
+    pub fn reduce(&mut self) {
+        while self.explode() || self.split() {}
+    }
+
+    pub fn magnitude(&self) -> i32 {
+        // Collapse pairs as soon as both halves are on the stack
+        let mut stack: Vec<(u32, i32)> = Vec::new();
+        for cell in self.0.iter() {
+            stack.push(*cell);
+            while stack.len() >= 2 && stack[stack.len() - 1].0 == stack[stack.len() - 2].0 {
+                let (d, r) = stack.pop().unwrap();
+                let (_, l) = stack.pop().unwrap();
+                stack.push((d - 1, 3 * l + 2 * r));
             }
         }
-        return (new_list, new_left.1, new_right.2, new_left.3 || new_right.3);
+        stack[0].1
     }
 }
 
-pub fn split(l: &List, mut found: bool) -> (List, bool) {
-    let mut new_list: List = l.clone();
-    if found {
-        return (new_list, true);
-    };
-    let new_left = match l.left.clone() {
-        EitherList::List(l2) => {
-            let res = split(&l2, found);
-            if res.1 {
-                found = true
-            };
-            EitherList::List(Box::new(res.0))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    L,
+    R,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    // Path to the exploding pair
+    Explode(Vec<Side>),
+    // Value split and the path to it
+    Split(i32, Vec<Side>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub action: Action,
+    pub result: List,
+}
+
+fn format_path(path: &[Side]) -> String {
+    path.iter()
+        .map(|s| format!("{:?}", s))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.action {
+            Action::Explode(path) => write!(f, "explode at path {}", format_path(path))?,
+            Action::Split(v, path) => write!(f, "split {} at path {}", v, format_path(path))?,
         }
-        EitherList::Value(v) => {
-            if v >= 10 {
-                found = true;
-                if v % 2 == 0 {
-                    EitherList::List(Box::new(List {
-                        left: EitherList::Value(v / 2),
-                        right: EitherList::Value(v / 2),
-                    }))
-                } else {
-                    EitherList::List(Box::new(List {
-                        left: EitherList::Value(v / 2),
-                        right: EitherList::Value((v / 2) + 1),
-                    }))
-                }
-            } else {
-                EitherList::Value(v)
+        write!(f, ": {}", self.result)
+    }
+}
+
+// Iterator over each action reduce takes, in order
+pub struct Reduction {
+    flat: FlatList,
+}
+
+impl Iterator for Reduction {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Step> {
+        let action = if let Some(i) = self.flat.explode_index() {
+            let mut path = self.flat.leaf_path(i);
+            path.pop();
+            self.flat.explode();
+            Action::Explode(path)
+        } else if let Some(i) = self.flat.split_index() {
+            let action = Action::Split(self.flat.0[i].1, self.flat.leaf_path(i));
+            self.flat.split();
+            action
+        } else {
+            return None;
+        };
+        Some(Step {
+            action,
+            result: List::from(&self.flat),
+        })
+    }
+}
+
+pub fn reduce_steps(l: &List) -> Reduction {
+    Reduction {
+        flat: FlatList::from(l),
+    }
+}
+
+fn flatten(e: &EitherList, depth: u32, out: &mut Vec<(u32, i32)>) {
+    match e {
+        EitherList::Value(v) => out.push((depth, *v)),
+        EitherList::List(l) => {
+            flatten(&l.left, depth + 1, out);
+            flatten(&l.right, depth + 1, out);
+        }
+    }
+}
+
+impl From<&List> for FlatList {
+    fn from(l: &List) -> Self {
+        let mut out = Vec::new();
+        flatten(&l.left, 1, &mut out);
+        flatten(&l.right, 1, &mut out);
+        FlatList(out)
+    }
+}
+
+impl From<&FlatList> for List {
+    fn from(f: &FlatList) -> Self {
+        // Same stack collapse as magnitude, building pairs instead
+        let mut stack: Vec<(u32, EitherList)> = Vec::new();
+        for (d, v) in f.0.iter() {
+            stack.push((*d, EitherList::Value(*v)));
+            while stack.len() >= 2 && stack[stack.len() - 1].0 == stack[stack.len() - 2].0 {
+                let (d, right) = stack.pop().unwrap();
+                let (_, left) = stack.pop().unwrap();
+                stack.push((d - 1, EitherList::List(Box::new(List { left, right }))));
             }
         }
-    };
-    new_list.left = new_left;
-    if found {
-        return (new_list, true);
-    };
-    let new_right = match l.right.clone() {
-        EitherList::List(l2) => {
-            let res = split(&l2, found);
-            if res.1 {
-                found = true
-            };
-
-            EitherList::List(Box::new(res.0))
+        match stack.pop() {
+            Some((_, EitherList::List(l))) if stack.is_empty() => *l,
+            _ => panic!("flat list is not a single pair"),
         }
-        EitherList::Value(v) => {
-            if v >= 10 {
-                found = true;
-                if v % 2 == 0 {
-                    EitherList::List(Box::new(List {
-                        left: EitherList::Value(v / 2),
-                        right: EitherList::Value(v / 2),
-                    }))
-                } else {
-                    EitherList::List(Box::new(List {
-                        left: EitherList::Value(v / 2),
-                        right: EitherList::Value((v / 2) + 1),
-                    }))
-                }
-            } else {
-                EitherList::Value(v)
-            }
+    }
+}
+
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{},{}]", self.left, self.right)
+    }
+}
+
+impl fmt::Display for EitherList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::List(l) => write!(f, "{}", l),
+            Self::Value(v) => write!(f, "{}", v),
         }
-    };
-    new_list.right = new_right;
-    (new_list, found)
+    }
 }
 
-pub fn reduce(l: &List) -> List {
-    let mut new_list = l.clone();
-    loop {
-        loop {
-            let prev_list = new_list.clone();
-            new_list = new_list.explode();
-            if prev_list == new_list {
-                break;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseListError {
+    UnexpectedEnd,
+    // Char position, the char found and what was expected there
+    Unexpected(usize, char, &'static str),
+    NumberTooLarge(usize),
+    TrailingInput(usize),
+}
+
+impl fmt::Display for ParseListError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "unexpected end of input"),
+            Self::Unexpected(pos, c, expected) => {
+                write!(f, "expected {} at {}, found {:?}", expected, pos, c)
             }
+            Self::NumberTooLarge(pos) => write!(f, "number at {} is too large", pos),
+            Self::TrailingInput(pos) => write!(f, "unexpected input after pair at {}", pos),
         }
+    }
+}
 
-        let res = split(&new_list, false);
-        new_list = res.0;
-        if !res.1 {
-            break;
+impl std::error::Error for ParseListError {}
+
+fn expect_char(chars: &[char], pos: &mut usize, want: char) -> Result<(), ParseListError> {
+    match chars.get(*pos) {
+        None => Err(ParseListError::UnexpectedEnd),
+        Some(c) if *c == want => {
+            *pos += 1;
+            Ok(())
         }
+        Some(c) => Err(ParseListError::Unexpected(
+            *pos,
+            *c,
+            match want {
+                '[' => "'['",
+                ',' => "','",
+                _ => "']'",
+            },
+        )),
     }
-    new_list
 }
 
-pub fn add_left(l: &List, v: i32) -> List {
-    let mut new_l = l.clone();
-    match l.left.clone() {
-        EitherList::Value(val) => {
-            new_l.left = EitherList::Value(val + v);
-        }
-        EitherList::List(l2) => {
-            new_l.left = EitherList::List(Box::new(add_left(&l2, v)));
+fn parse_pair(chars: &[char], pos: &mut usize) -> Result<List, ParseListError> {
+    expect_char(chars, pos, '[')?;
+    let left = parse_element(chars, pos)?;
+    expect_char(chars, pos, ',')?;
+    let right = parse_element(chars, pos)?;
+    expect_char(chars, pos, ']')?;
+    Ok(List { left, right })
+}
+
+fn parse_element(chars: &[char], pos: &mut usize) -> Result<EitherList, ParseListError> {
+    match chars.get(*pos) {
+        None => Err(ParseListError::UnexpectedEnd),
+        Some('[') => Ok(EitherList::List(Box::new(parse_pair(chars, pos)?))),
+        Some(c) if c.is_ascii_digit() => {
+            let start = *pos;
+            let mut v: i32 = 0;
+            while let Some(d) = chars.get(*pos).and_then(|c| c.to_digit(10)) {
+                v = v
+                    .checked_mul(10)
+                    .and_then(|v| v.checked_add(d as i32))
+                    .ok_or(ParseListError::NumberTooLarge(start))?;
+                *pos += 1;
+            }
+            Ok(EitherList::Value(v))
         }
+        Some(c) => Err(ParseListError::Unexpected(*pos, *c, "'[' or a digit")),
     }
-    new_l
 }
-pub fn add_right(l: &List, v: i32) -> List {
-    let mut new_l = l.clone();
-    match l.right.clone() {
-        EitherList::Value(val) => {
-            new_l.right = EitherList::Value(val + v);
-        }
-        EitherList::List(l2) => {
-            new_l.right = EitherList::List(Box::new(add_right(&l2, v)));
+
+impl FromStr for List {
+    type Err = ParseListError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chars: Vec<char> = s.chars().collect();
+        let mut pos = 0;
+        let list = parse_pair(&chars, &mut pos)?;
+        if pos == chars.len() {
+            Ok(list)
+        } else {
+            Err(ParseListError::TrailingInput(pos))
         }
     }
-    new_l
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum EitherList {
-    List(Box<List>),
-    Value(i32),
+// Reduced addition. List::pair is the plain pair without reducing.
+impl Add for List {
+    type Output = List;
+
+    fn add(self, other: List) -> List {
+        let mut sum = FlatList::from(&self);
+        sum.add(&FlatList::from(&other));
+        sum.reduce();
+        List::from(&sum)
+    }
 }
 
-impl EitherList {
-    pub fn magnitude(&self) -> i32 {
-        match self {
-            Self::List(l) => l.magnitude(),
-            Self::Value(v) => *v,
-        }
+// Panics on an empty iterator, as there's no zero snailfish number
+impl Sum for List {
+    fn sum<I: Iterator<Item = List>>(iter: I) -> List {
+        let sum = iter
+            .map(|l| FlatList::from(&l))
+            .reduce(|mut x, y| {
+                x.add(&y);
+                x.reduce();
+                x
+            })
+            .expect("can't sum no snailfish numbers");
+        List::from(&sum)
     }
 }
 
@@ -232,70 +450,34 @@ impl EitherList {
 pub fn input_generator(input: &str) -> Vec<List> {
     input
         .lines()
-        .map(|l| {
-            // Skip first char - we know it must be '[' so we start inside List
-            let mut it = l.chars();
-            it.next();
-            recurse_list(&mut it)
-        })
+        .map(|l| l.parse().expect("invalid snailfish number"))
         .collect()
 }
 
-pub fn recurse_list<I>(input: &mut I) -> List
-where
-    I: Iterator<Item = char>,
-{
-    let left = iter_chars(input).expect("Left was none");
-    let right = iter_chars(input).expect("Right was none");
-
-    List { left, right }
-}
-pub fn iter_chars<I>(input: &mut I) -> Option<EitherList>
-where
-    I: Iterator<Item = char>,
-{
-    let mut num: Option<i32> = None;
-    loop {
-        match input.next() {
-            None => break None,
-            Some('[') => break Some(EitherList::List(Box::new(recurse_list(input)))),
-            Some(']') => {
-                if let Some(n) = num {
-                    break Some(EitherList::Value(n));
-                }
-            }
-            Some(',') => {
-                if let Some(n) = num {
-                    break Some(EitherList::Value(n));
-                }
-            }
-            Some(c) => {
-                let mut temp_num = num.unwrap_or(0);
-                temp_num *= 10;
-                temp_num += c.to_digit(10).expect("char not digit") as i32;
-                num = Some(temp_num)
-            }
-        }
-    }
-}
-
 #[aoc(day18, part1)]
 pub fn solve_part1(input: &[List]) -> i32 {
-    let input_vec: Vec<List> = input.iter().map(|x| x.clone()).collect();
-    input_vec
-        .into_iter()
-        .reduce(|x, y| reduce(&x.add(y)))
+    input
+        .iter()
+        .map(FlatList::from)
+        .reduce(|mut x, y| {
+            x.add(&y);
+            x.reduce();
+            x
+        })
         .unwrap()
         .magnitude()
 }
 #[aoc(day18, part2)]
 pub fn solve_part2(input: &[List]) -> i32 {
-    let input_vec: Vec<List> = input.iter().map(|x| x.clone()).collect();
+    let flat: Vec<FlatList> = input.iter().map(FlatList::from).collect();
     let mut max_magnitude: i32 = 0;
-    for i in 0..input_vec.len() {
-        for j in 0..input_vec.len() {
+    for i in 0..flat.len() {
+        for j in 0..flat.len() {
             if i != j {
-                let mag = reduce(&input_vec[i].clone().add(input_vec[j].clone())).magnitude();
+                let mut sum = flat[i].clone();
+                sum.add(&flat[j]);
+                sum.reduce();
+                let mag = sum.magnitude();
                 if mag > max_magnitude {
                     max_magnitude = mag;
                 }
@@ -469,6 +651,52 @@ mod tests {
         assert_eq!(parsed[0].clone().explode(), parsedout[0]);
     }
     #[test]
+    fn test_explode_deep() {
+        // Only reachable from parsed input: addition never nests pairs more than five deep
+        let mut flat = FlatList::from(&"[[[[[[1,2],[3,4]],0],0],0],0]".parse::<List>().unwrap());
+        assert!(flat.explode());
+        assert_eq!(
+            List::from(&flat),
+            "[[[[[0,[5,4]],0],0],0],0]".parse::<List>().unwrap()
+        );
+        flat.reduce();
+        assert_eq!(
+            List::from(&flat),
+            "[[[[0,4],0],0],0]".parse::<List>().unwrap()
+        );
+
+        let l: List = "[[[[[1,[2,3]],0],0],0],0]".parse().unwrap();
+        assert_eq!(l.explode(), "[[[[[3,0],3],0],0],0]".parse().unwrap());
+    }
+    #[test]
+    fn test_tree_helpers() {
+        let parse = |s: &str| s.parse::<List>().unwrap();
+        // Inside three pairs, so [9,8] is inside four and its 9 falls off the left
+        let mut l = parse("[[9,8],1]");
+        assert_eq!(
+            recurse_down(&mut l, 3, false),
+            (parse("[0,9]"), Some(9), None, true)
+        );
+        assert_eq!(
+            recurse_down(&mut parse("[[[[[9,8],1],2],3],4]"), 0, false),
+            (parse("[[[[0,9],2],3],4]"), Some(9), None, true)
+        );
+        assert_eq!(
+            recurse_down(&mut l, 0, false),
+            (l.clone(), None, None, false)
+        );
+        assert_eq!(
+            recurse_down(&mut parse("[1,2]"), 4, false),
+            (parse("[1,2]"), None, None, false)
+        );
+        assert_eq!(recurse_down(&mut l, 3, true), (l.clone(), None, None, true));
+        assert_eq!(split(&parse("[11,1]"), false), (parse("[[5,6],1]"), true));
+        assert_eq!(split(&parse("[11,1]"), true), (parse("[11,1]"), true));
+        assert_eq!(add_left(&parse("[[1,2],3]"), 5), parse("[[6,2],3]"));
+        assert_eq!(add_right(&parse("[[1,2],3]"), 5), parse("[[1,2],8]"));
+    }
+    #[test]
+    #[allow(deprecated)]
     fn test_add() {
         let inp1 = "[[[[4,3],4],4],[7,[[8,4],9]]]";
         let parsed1 = input_generator(inp1);
@@ -479,6 +707,7 @@ mod tests {
         assert_eq!(parsed1[0].clone().add(parsed2[0].clone()), parsedout[0]);
     }
     #[test]
+    #[allow(deprecated)]
     fn test_reduce() {
         let inp1 = "[[[[4,3],4],4],[7,[[8,4],9]]]";
         let parsed1 = input_generator(inp1);
@@ -503,6 +732,7 @@ mod tests {
         assert_eq!(solve_part1(&parsed1), parsedout[0].magnitude());
     }
     #[test]
+    #[allow(deprecated)]
     fn test_add_medium() {
         let inp1 = "[[[0,[4,5]],[0,0]],[[[4,5],[2,6]],[9,5]]]";
         let inp2 = "[7,[[[3,7],[4,3]],[[6,3],[8,8]]]]";
@@ -567,4 +797,101 @@ mod tests {
         let parsed1 = input_generator(inp1);
         assert_eq!(solve_part2(&parsed1), 3993);
     }
+    #[test]
+    fn test_flat() {
+        let inp = "[[[9,[3,8]],[[0,9],6]],[[[3,7],[4,9]],3]]
+[[[[[4,3],4],4],[7,[[8,4],9]]],[1,1]]";
+        let parsed = input_generator(inp);
+        let flat = FlatList::from(&parsed[0]);
+        assert_eq!(flat.0[..3], [(3, 9), (4, 3), (4, 8)]);
+        assert_eq!(List::from(&flat), parsed[0]);
+        assert_eq!(flat.magnitude(), parsed[0].magnitude());
+
+        let mut flat = FlatList::from(&parsed[1]);
+        assert!(flat.explode());
+        assert_eq!(
+            List::from(&flat),
+            input_generator("[[[[0,7],4],[7,[[8,4],9]]],[1,1]]")[0]
+        );
+        flat.reduce();
+        assert!(!flat.explode() && !flat.split());
+        assert_eq!(
+            List::from(&flat),
+            input_generator("[[[[0,7],4],[[7,8],[6,0]]],[8,1]]")[0]
+        );
+    }
+    #[test]
+    fn test_display() {
+        for inp in [
+            "[1,2]",
+            "[[[9,[3,8]],[[0,9],6]],[[[3,7],[4,9]],3]]",
+            "[[[[0,7],4],[15,[0,13]]],[1,1]]",
+        ] {
+            let parsed: List = inp.parse().unwrap();
+            assert_eq!(parsed.to_string(), inp);
+        }
+    }
+    #[test]
+    fn test_parse_errors() {
+        use ParseListError::*;
+        assert_eq!("[1,2".parse::<List>(), Err(UnexpectedEnd));
+        assert_eq!("".parse::<List>(), Err(UnexpectedEnd));
+        assert_eq!("1".parse::<List>(), Err(Unexpected(0, '1', "'['")));
+        assert_eq!("[1;2]".parse::<List>(), Err(Unexpected(2, ';', "','")));
+        assert_eq!(
+            "[[1,2],x]".parse::<List>(),
+            Err(Unexpected(7, 'x', "'[' or a digit"))
+        );
+        assert_eq!("[1,2,3]".parse::<List>(), Err(Unexpected(4, ',', "']'")));
+        assert_eq!("[1,2]]".parse::<List>(), Err(TrailingInput(5)));
+        assert_eq!("[1,99999999999]".parse::<List>(), Err(NumberTooLarge(3)));
+        assert_eq!(
+            "[1;2]".parse::<List>().unwrap_err().to_string(),
+            "expected ',' at 2, found ';'"
+        );
+    }
+    #[test]
+    fn test_add_sum() {
+        let a: List = "[[[[4,3],4],4],[7,[[8,4],9]]]".parse().unwrap();
+        let b: List = "[1,1]".parse().unwrap();
+        assert_eq!((a + b).to_string(), "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]");
+
+        let inp = "[[[0,[5,8]],[[1,7],[9,6]]],[[4,[1,2]],[[1,4],2]]]
+[[[5,[2,8]],4],[5,[[9,9],0]]]
+[6,[[[6,2],[5,6]],[[7,6],[4,7]]]]
+[[[6,[0,7]],[0,9]],[4,[9,[9,0]]]]
+[[[7,[6,4]],[3,[1,3]]],[[[5,5],1],9]]
+[[6,[[7,3],[3,2]]],[[[3,8],[5,7]],4]]
+[[[[5,4],[7,7]],8],[[8,3],8]]
+[[9,3],[[9,9],[6,[4,9]]]]
+[[2,[[7,7],7]],[[5,8],[[9,3],[0,2]]]]
+[[[[5,2],5],[8,[3,7]]],[[5,[7,5]],[4,4]]]";
+        let sum: List = input_generator(inp).into_iter().sum();
+        assert_eq!(
+            sum.to_string(),
+            "[[[[6,6],[7,6]],[[7,7],[7,0]]],[[[7,7],[7,7]],[[7,8],[9,9]]]]"
+        );
+        assert_eq!(sum.magnitude(), 4140);
+    }
+    #[test]
+    fn test_reduce_steps() {
+        let a: List = "[[[[4,3],4],4],[7,[[8,4],9]]]".parse().unwrap();
+        let b: List = "[1,1]".parse().unwrap();
+        let steps: Vec<String> = reduce_steps(&a.pair(b)).map(|s| s.to_string()).collect();
+        assert_eq!(
+            steps,
+            vec![
+                "explode at path L,L,L,L: [[[[0,7],4],[7,[[8,4],9]]],[1,1]]",
+                "explode at path L,R,R,L: [[[[0,7],4],[15,[0,13]]],[1,1]]",
+                "split 15 at path L,R,L: [[[[0,7],4],[[7,8],[0,13]]],[1,1]]",
+                "split 13 at path L,R,R,R: [[[[0,7],4],[[7,8],[0,[6,7]]]],[1,1]]",
+                "explode at path L,R,R,R: [[[[0,7],4],[[7,8],[6,0]]],[8,1]]",
+            ]
+        );
+        let flat = FlatList::from(&"[[1,[2,3]],[4,5]]".parse::<List>().unwrap());
+        use Side::*;
+        assert_eq!(flat.leaf_path(0), vec![L, L]);
+        assert_eq!(flat.leaf_path(2), vec![L, R, R]);
+        assert_eq!(flat.leaf_path(3), vec![R, L]);
+    }
 }
@@ -1,14 +1,15 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use regex::Regex;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
 type Pos = (i32, i32, i32);
 // https://www.euclideanspace.com/maths/algebra/matrix/transforms/examples/index.htm
-#[derive(Debug, EnumIter, PartialEq, Clone, Copy)]
-pub enum Rotation {
+#[derive(Debug, EnumIter, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Orientation {
     Nothing,
     X90,
     X180,
@@ -35,9 +36,9 @@ pub enum Rotation {
     Y270Z90,
 }
 
-impl Rotation {
+impl Orientation {
     pub fn apply(&self, pos: Pos) -> Pos {
-        use Rotation::*;
+        use Orientation::*;
         match self {
             Nothing => pos,
             X90 => (pos.0, -pos.2, pos.1),
@@ -72,6 +73,101 @@ impl Rotation {
     }
 }
 
+// Signed permutation matrix with determinant 1, acting on column vectors
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Rotation([[i32; 3]; 3]);
+
+impl Rotation {
+    pub fn identity() -> Rotation {
+        Rotation([[1, 0, 0], [0, 1, 0], [0, 0, 1]])
+    }
+
+    // All 24 rotations, in the same order as Orientation::iter()
+    pub fn all() -> Vec<Rotation> {
+        Orientation::iter().map(Rotation::from).collect()
+    }
+
+    // Only accepts matrices which are one of the 24 axis-aligned rotations
+    pub fn from_matrix(m: [[i32; 3]; 3]) -> Option<Rotation> {
+        let is_unit = |v: [i32; 3]| v.iter().map(|x| x.abs()).sum::<i32>() == 1;
+        let cols = (0..3).map(|c| [m[0][c], m[1][c], m[2][c]]);
+        if !m.iter().all(|r| is_unit(*r)) || !cols.into_iter().all(is_unit) {
+            return None;
+        }
+        let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+        if det == 1 {
+            Some(Rotation(m))
+        } else {
+            None
+        }
+    }
+
+    pub fn matrix(&self) -> [[i32; 3]; 3] {
+        self.0
+    }
+
+    pub fn apply(&self, pos: Pos) -> Pos {
+        let m = &self.0;
+        let v = [pos.0, pos.1, pos.2];
+        let row = |r: usize| m[r][0] * v[0] + m[r][1] * v[1] + m[r][2] * v[2];
+        (row(0), row(1), row(2))
+    }
+
+    // Rotation applying `other` first and then `self`
+    pub fn compose(&self, other: &Rotation) -> Rotation {
+        let mut out = [[0; 3]; 3];
+        for (r, row) in out.iter_mut().enumerate() {
+            for (c, cell) in row.iter_mut().enumerate() {
+                *cell = (0..3).map(|k| self.0[r][k] * other.0[k][c]).sum();
+            }
+        }
+        Rotation(out)
+    }
+
+    // Orthogonal, so the inverse is the transpose
+    pub fn inverse(&self) -> Rotation {
+        let mut out = [[0; 3]; 3];
+        for (r, row) in out.iter_mut().enumerate() {
+            for (c, cell) in row.iter_mut().enumerate() {
+                *cell = self.0[c][r];
+            }
+        }
+        Rotation(out)
+    }
+}
+
+impl std::ops::Mul for Rotation {
+    type Output = Rotation;
+
+    fn mul(self, other: Rotation) -> Rotation {
+        self.compose(&other)
+    }
+}
+
+impl From<Orientation> for Rotation {
+    fn from(o: Orientation) -> Rotation {
+        // Columns are the images of the unit vectors
+        let cols = [o.apply((1, 0, 0)), o.apply((0, 1, 0)), o.apply((0, 0, 1))];
+        let mut m = [[0; 3]; 3];
+        for (c, col) in cols.iter().enumerate() {
+            m[0][c] = col.0;
+            m[1][c] = col.1;
+            m[2][c] = col.2;
+        }
+        Rotation(m)
+    }
+}
+
+impl From<Rotation> for Orientation {
+    fn from(r: Rotation) -> Orientation {
+        Orientation::iter()
+            .find(|o| Rotation::from(*o) == r)
+            .expect("every rotation has an orientation")
+    }
+}
+
 #[aoc_generator(day19)]
 pub fn input_generator(input: &str) -> Vec<Vec<(i32, i32, i32)>> {
     input
@@ -101,6 +197,26 @@ pub struct SolvedScanner {
     raw_relative_points: HashSet<Pos>,
     transformed_points_from_origin: HashSet<Pos>,
     label: usize,
+    // Label of the scanner this one was aligned against
+    parent: Option<usize>,
+}
+
+impl SolvedScanner {
+    pub fn label(&self) -> usize {
+        self.label
+    }
+
+    pub fn position(&self) -> Pos {
+        self.position
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -109,109 +225,339 @@ pub struct UnsolvedScanner {
     raw_relative_points: HashSet<Pos>,
 }
 
-pub fn solve_scanners(input: &Vec<Vec<(i32, i32, i32)>>) -> (Vec<SolvedScanner>, HashSet<Pos>) {
-    let mut solved_points = HashSet::new();
-    let mut solved_scanners = Vec::new();
-    let mut unsolved_scanners = Vec::new();
-    for p in input[0].iter() {
-        solved_points.insert(p.clone());
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignmentError {
+    // Labels of scanners that could not be aligned with any solved scanner
+    pub unmatched: Vec<usize>,
+}
+
+impl fmt::Display for AlignmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "could not align scanners {:?}", self.unmatched)
     }
+}
 
-    solved_scanners.push(SolvedScanner {
+impl std::error::Error for AlignmentError {}
+
+// Multiset of squared distances between every pair of points, which doesn't depend on the
+// scanner's position or rotation
+pub fn fingerprint(points: &HashSet<Pos>) -> HashMap<i64, usize> {
+    let points: Vec<&Pos> = points.iter().collect();
+    let mut out = HashMap::new();
+    for (i, a) in points.iter().enumerate() {
+        for b in points[i + 1..].iter() {
+            let d = [a.0 - b.0, a.1 - b.1, a.2 - b.2]
+                .iter()
+                .map(|x| (*x as i64) * (*x as i64))
+                .sum::<i64>();
+            *out.entry(d).or_insert(0) += 1;
+        }
+    }
+    out
+}
+
+// Scanners sharing `threshold` beacons share at least threshold * (threshold - 1) / 2 distances
+pub fn may_overlap(a: &HashMap<i64, usize>, b: &HashMap<i64, usize>, threshold: usize) -> bool {
+    let common: usize = a.iter().map(|(d, n)| *n.min(b.get(d).unwrap_or(&0))).sum();
+    common >= threshold * threshold.saturating_sub(1) / 2
+}
+
+// Finds the rotation and origin placing at least `threshold` of the unsolved scanner's points on
+// the solved scanner's points
+pub fn align(
+    solved: &SolvedScanner,
+    unsolved: &UnsolvedScanner,
+    threshold: usize,
+) -> Option<SolvedScanner> {
+    for rot in Rotation::all() {
+        let transformed_points: Vec<Pos> = unsolved
+            .raw_relative_points
+            .iter()
+            .map(|x| rot.apply(*x))
+            .collect();
+
+        // new scanner origin + transformed pos = solved transformed pos from origin
+        // so: new scanner origin = solved transformed pos from origin - transformed pos
+        // Count how many point pairs agree on each possible origin
+        let mut origins: HashMap<Pos, usize> = HashMap::new();
+        for pu in transformed_points.iter() {
+            for ps in solved.transformed_points_from_origin.iter() {
+                *origins
+                    .entry((ps.0 - pu.0, ps.1 - pu.1, ps.2 - pu.2))
+                    .or_insert(0) += 1;
+            }
+        }
+
+        if let Some((o, _)) = origins.into_iter().find(|(_, n)| *n >= threshold) {
+            return Some(SolvedScanner {
+                position: o,
+                rotation: rot,
+                raw_relative_points: unsolved.raw_relative_points.clone(),
+                transformed_points_from_origin: transformed_points
+                    .iter()
+                    .map(|x| (o.0 + x.0, o.1 + x.1, o.2 + x.2))
+                    .collect(),
+                label: unsolved.label,
+                parent: Some(solved.label),
+            });
+        }
+    }
+    None
+}
+
+// Aligns every scanner to scanner 0, breadth first from each newly solved scanner
+fn solve_by<F>(input: &[Vec<Pos>], align: F) -> Result<Vec<SolvedScanner>, AlignmentError>
+where
+    F: Fn(&SolvedScanner, &UnsolvedScanner) -> Option<SolvedScanner>,
+{
+    let mut solved_scanners = vec![SolvedScanner {
         position: (0, 0, 0),
-        rotation: Rotation::Nothing,
-        raw_relative_points: HashSet::from_iter(input[0].iter().map(|x| *x)),
-        transformed_points_from_origin: HashSet::from_iter(input[0].iter().map(|x| *x)),
+        rotation: Rotation::identity(),
+        raw_relative_points: input[0].iter().copied().collect(),
+        transformed_points_from_origin: input[0].iter().copied().collect(),
         label: 0,
-    });
-
-    for i in 1..input.len() {
-        unsolved_scanners.push(UnsolvedScanner {
-            raw_relative_points: HashSet::from_iter(input[i].iter().map(|x| *x)),
+        parent: None,
+    }];
+    let mut unsolved_scanners: Vec<UnsolvedScanner> = (1..input.len())
+        .map(|i| UnsolvedScanner {
+            raw_relative_points: input[i].iter().copied().collect(),
             label: i,
-        });
-    }
-
-    let mut found: Option<SolvedScanner> = None;
-
-    while !unsolved_scanners.is_empty() {
-        for unsolved in unsolved_scanners.iter() {
-            for solved in solved_scanners.iter() {
-                for rot in Rotation::iter() {
-                    let transformed_points: HashSet<Pos> = unsolved
-                        .raw_relative_points
-                        .iter()
-                        .map(|x| rot.apply(*x))
-                        .collect();
-
-                    // new scanner origin + transformed pos = solved transformed pos from origin
-                    // so: new scanner origin = solved transformed pos from origin - transformed pos
-                    // Calculate all differences between point sets - all possible origins
-                    // Does any origin have 12 or more overlapping points?
-                    // If so, origin + rotation is correct
-
-                    let mut possible_origins = Vec::new();
-                    for pu in transformed_points.iter() {
-                        for ps in solved.transformed_points_from_origin.iter() {
-                            possible_origins.push((ps.0 - pu.0, ps.1 - pu.1, ps.2 - pu.2));
-                        }
-                    }
+        })
+        .collect();
+
+    // Each solved scanner is used once as a reference for all the remaining unsolved ones
+    let mut next_reference = 0;
+    while next_reference < solved_scanners.len() && !unsolved_scanners.is_empty() {
+        let solved = solved_scanners[next_reference].clone();
+        next_reference += 1;
+        let mut still_unsolved = Vec::new();
+        for unsolved in unsolved_scanners.into_iter() {
+            match align(&solved, &unsolved) {
+                Some(f) => solved_scanners.push(f),
+                None => still_unsolved.push(unsolved),
+            }
+        }
+        unsolved_scanners = still_unsolved;
+    }
 
-                    for o in possible_origins {
-                        let new_set: HashSet<Pos> = transformed_points
-                            .iter()
-                            .map(|x| (o.0 + x.0, o.1 + x.1, o.2 + x.2))
-                            .collect();
-
-                        let intersect =
-                            new_set.intersection(&solved.transformed_points_from_origin);
-                        if intersect.count() >= 12 {
-                            // println!(
-                            //     "Solved: s: {:?} u: {:?} new_origin: {:?}",
-                            //     &solved.label, &unsolved.label, &o
-                            // );
-                            found = Some(SolvedScanner {
-                                position: o,
-                                raw_relative_points: unsolved.raw_relative_points.clone(),
-                                transformed_points_from_origin: new_set.clone(),
-                                rotation: rot,
-                                label: unsolved.label,
-                            });
-                            break;
-                        }
+    if unsolved_scanners.is_empty() {
+        Ok(solved_scanners)
+    } else {
+        Err(AlignmentError {
+            unmatched: unsolved_scanners.iter().map(|u| u.label).collect(),
+        })
+    }
+}
+
+pub fn solve_scanners(
+    input: &[Vec<Pos>],
+    threshold: usize,
+) -> Result<(Vec<SolvedScanner>, HashSet<Pos>), AlignmentError> {
+    let fingerprints: Vec<HashMap<i64, usize>> = input
+        .iter()
+        .map(|v| fingerprint(&v.iter().copied().collect()))
+        .collect();
+    let solved_scanners = solve_by(input, |solved, unsolved| {
+        if may_overlap(
+            &fingerprints[solved.label],
+            &fingerprints[unsolved.label],
+            threshold,
+        ) {
+            align(solved, unsolved, threshold)
+        } else {
+            None
+        }
+    })?;
+    let solved_points = solved_scanners
+        .iter()
+        .flat_map(|s| s.transformed_points_from_origin.iter().copied())
+        .collect();
+    Ok((solved_scanners, solved_points))
+}
+
+// Matched beacons differ by the jitter of each report plus the error in the estimated translation
+fn match_tolerance(jitter: i32) -> i32 {
+    3 * jitter
+}
+
+// Translations implied by two matching pairs each carry the jitter of both reports
+fn translation_tolerance(jitter: i32) -> i32 {
+    4 * jitter
+}
+
+fn chebyshev(a: Pos, b: Pos) -> i32 {
+    (a.0 - b.0)
+        .abs()
+        .max((a.1 - b.1).abs())
+        .max((a.2 - b.2).abs())
+}
+
+// Translation agreed on by the most candidates within `tolerance` of each other, refined to the
+// rounded mean of its supporters (the least squares fit). Candidates are bucketed so only
+// neighbouring cells are compared.
+pub fn consensus_translation(candidates: &[Pos], tolerance: i32) -> Option<(Pos, usize)> {
+    let cell = tolerance.max(1);
+    let key = |p: &Pos| {
+        (
+            p.0.div_euclid(cell),
+            p.1.div_euclid(cell),
+            p.2.div_euclid(cell),
+        )
+    };
+    let mut grid: BTreeMap<Pos, Vec<Pos>> = BTreeMap::new();
+    for c in candidates {
+        grid.entry(key(c)).or_default().push(*c);
+    }
+    let neighbourhood = |k: &Pos| -> Vec<Pos> {
+        let mut out = Vec::new();
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if let Some(v) = grid.get(&(k.0 + dx, k.1 + dy, k.2 + dz)) {
+                        out.extend(v);
                     }
                 }
             }
-            if found.is_some() {
-                break;
+        }
+        out
+    };
+    let agree = |a: &Pos, b: &Pos| chebyshev(*a, *b) <= tolerance;
+
+    // Gather each bucket's neighbours once and score all of its candidates against them
+    let mut best: Option<(usize, Pos, Pos)> = None;
+    for (k, members) in &grid {
+        let pool = neighbourhood(k);
+        for c in members {
+            let count = pool.iter().filter(|o| agree(o, c)).count();
+            if !matches!(best, Some((n, _, _)) if n >= count) {
+                best = Some((count, *k, *c));
             }
         }
+    }
+    let (_, k, c) = best?;
+    let support: Vec<Pos> = neighbourhood(&k)
+        .into_iter()
+        .filter(|o| agree(o, &c))
+        .collect();
+    let n = support.len() as f64;
+    let mean = |f: fn(&Pos) -> i32| (support.iter().map(f).sum::<i32>() as f64 / n).round() as i32;
+    Some(((mean(|p| p.0), mean(|p| p.1), mean(|p| p.2)), support.len()))
+}
 
-        if let Some(f) = &found {
-            solved_scanners.push(f.clone());
-            unsolved_scanners.retain(|x| x.label != f.label);
+// Number of points which land within `tolerance` of a distinct reference point
+fn count_inliers(reference: &[Pos], points: &[Pos], tolerance: i32) -> usize {
+    let mut used = vec![false; reference.len()];
+    let mut inliers = 0;
+    for p in points {
+        let nearest = (0..reference.len())
+            .filter(|i| !used[*i])
+            .map(|i| (chebyshev(reference[i], *p), i))
+            .min();
+        if let Some((d, i)) = nearest {
+            if d <= tolerance {
+                used[i] = true;
+                inliers += 1;
+            }
+        }
+    }
+    inliers
+}
 
-            // println!("Solved: {:?}", &solved_scanners);
+// Like align, but beacon coordinates may be off by up to `jitter` on each axis and either scanner
+// may have missing or spurious beacons. Picks the rotation whose consensus translation puts the
+// most points within tolerance of a reference point.
+pub fn align_noisy(
+    solved: &SolvedScanner,
+    unsolved: &UnsolvedScanner,
+    jitter: i32,
+    threshold: usize,
+) -> Option<SolvedScanner> {
+    let tolerance = match_tolerance(jitter);
+    let reference: Vec<Pos> = solved
+        .transformed_points_from_origin
+        .iter()
+        .copied()
+        .collect();
+    let raw: Vec<Pos> = unsolved.raw_relative_points.iter().copied().collect();
 
-            for p in f.transformed_points_from_origin.iter() {
-                solved_points.insert(*p);
-            }
+    let mut best: Option<(usize, Rotation, Pos)> = None;
+    for rot in Rotation::all() {
+        let transformed: Vec<Pos> = raw.iter().map(|x| rot.apply(*x)).collect();
+        let candidates: Vec<Pos> = transformed
+            .iter()
+            .flat_map(|pu| {
+                reference
+                    .iter()
+                    .map(move |ps| (ps.0 - pu.0, ps.1 - pu.1, ps.2 - pu.2))
+            })
+            .collect();
+        let (o, support) = match consensus_translation(&candidates, translation_tolerance(jitter)) {
+            Some(c) => c,
+            None => continue,
+        };
+        if support < threshold {
+            continue;
+        }
+        let moved: Vec<Pos> = transformed
+            .iter()
+            .map(|x| (o.0 + x.0, o.1 + x.1, o.2 + x.2))
+            .collect();
+        let inliers = count_inliers(&reference, &moved, tolerance);
+        if inliers >= threshold && !matches!(best, Some((n, _, _)) if n >= inliers) {
+            best = Some((inliers, rot, o));
         }
-        found = None
     }
 
-    (solved_scanners, solved_points)
+    let (_, rot, o) = best?;
+    Some(SolvedScanner {
+        position: o,
+        rotation: rot,
+        raw_relative_points: unsolved.raw_relative_points.clone(),
+        transformed_points_from_origin: raw
+            .iter()
+            .map(|x| rot.apply(*x))
+            .map(|x| (o.0 + x.0, o.1 + x.1, o.2 + x.2))
+            .collect(),
+        label: unsolved.label,
+        parent: Some(solved.label),
+    })
+}
+
+// Noisy version of solve_scanners. Beacons reported by several scanners are merged when they're
+// within tolerance of one already seen, keeping the first report.
+pub fn solve_scanners_noisy(
+    input: &[Vec<Pos>],
+    jitter: i32,
+    threshold: usize,
+) -> Result<(Vec<SolvedScanner>, Vec<Pos>), AlignmentError> {
+    let solved_scanners = solve_by(input, |solved, unsolved| {
+        align_noisy(solved, unsolved, jitter, threshold)
+    })?;
+    let mut beacons: Vec<Pos> = Vec::new();
+    for s in solved_scanners.iter() {
+        let mut points: Vec<Pos> = s.transformed_points_from_origin.iter().copied().collect();
+        points.sort();
+        for p in points {
+            if beacons
+                .iter()
+                .all(|b| chebyshev(*b, p) > match_tolerance(jitter))
+            {
+                beacons.push(p);
+            }
+        }
+    }
+    Ok((solved_scanners, beacons))
 }
 
 #[aoc(day19, part1)]
-pub fn solve_part1(input: &Vec<Vec<(i32, i32, i32)>>) -> usize {
-    let (_, solved_points) = solve_scanners(input);
+pub fn solve_part1(input: &[Vec<Pos>]) -> usize {
+    let (_, solved_points) = solve_scanners(input, 12).unwrap();
     solved_points.len()
 }
 
 #[aoc(day19, part2)]
-pub fn solve_part2(input: &Vec<Vec<(i32, i32, i32)>>) -> i32 {
-    let (solved_scanners, _) = solve_scanners(input);
+pub fn solve_part2(input: &[Vec<Pos>]) -> i32 {
+    let (solved_scanners, _) = solve_scanners(input, 12).unwrap();
 
     let mut max_dist: i32 = 0;
     for s1 in &solved_scanners {
@@ -225,6 +571,104 @@ pub fn solve_part2(input: &Vec<Vec<(i32, i32, i32)>>) -> i32 {
     max_dist
 }
 
+// Pose of `child` in the coordinate frame of `parent`, so that
+// parent local pos = rotation.apply(child local pos) + translation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoseEdge {
+    pub parent: usize,
+    pub child: usize,
+    pub rotation: Rotation,
+    pub translation: Pos,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScannerMap {
+    // Ordered by label
+    pub scanners: Vec<SolvedScanner>,
+    // In the order the scanners were aligned
+    pub edges: Vec<PoseEdge>,
+    // Merged beacons relative to scanner 0, sorted
+    pub beacons: Vec<Pos>,
+}
+
+pub fn map_scanners(input: &[Vec<Pos>], threshold: usize) -> Result<ScannerMap, AlignmentError> {
+    let (solved, points) = solve_scanners(input, threshold)?;
+    let by_label: HashMap<usize, &SolvedScanner> = solved.iter().map(|s| (s.label, s)).collect();
+
+    let edges = solved
+        .iter()
+        .filter_map(|child| {
+            let parent = by_label[&child.parent?];
+            let inv = parent.rotation.inverse();
+            let offset = (
+                child.position.0 - parent.position.0,
+                child.position.1 - parent.position.1,
+                child.position.2 - parent.position.2,
+            );
+            Some(PoseEdge {
+                parent: parent.label,
+                child: child.label,
+                rotation: inv * child.rotation,
+                translation: inv.apply(offset),
+            })
+        })
+        .collect();
+
+    let mut scanners = solved;
+    scanners.sort_by_key(|s| s.label);
+    let mut beacons: Vec<Pos> = points.into_iter().collect();
+    beacons.sort();
+
+    Ok(ScannerMap {
+        scanners,
+        edges,
+        beacons,
+    })
+}
+
+impl ScannerMap {
+    // One "x y z" line per beacon
+    pub fn to_xyz(&self) -> String {
+        self.beacons
+            .iter()
+            .map(|b| format!("{} {} {}\n", b.0, b.1, b.2))
+            .collect()
+    }
+
+    // ASCII PLY with white beacons, red scanners after them, and the pose graph as edges between
+    // scanner vertices
+    pub fn to_ply(&self) -> String {
+        let mut out = String::new();
+        out.push_str("ply\nformat ascii 1.0\n");
+        out.push_str(&format!(
+            "element vertex {}\n",
+            self.beacons.len() + self.scanners.len()
+        ));
+        for p in ["x", "y", "z"] {
+            out.push_str(&format!("property int {}\n", p));
+        }
+        for p in ["red", "green", "blue"] {
+            out.push_str(&format!("property uchar {}\n", p));
+        }
+        out.push_str(&format!("element edge {}\n", self.edges.len()));
+        out.push_str("property int vertex1\nproperty int vertex2\nend_header\n");
+
+        for b in self.beacons.iter() {
+            out.push_str(&format!("{} {} {} 255 255 255\n", b.0, b.1, b.2));
+        }
+        let mut vertex = HashMap::new();
+        for s in self.scanners.iter() {
+            vertex.insert(s.label, self.beacons.len() + vertex.len());
+            let p = s.position;
+            out.push_str(&format!("{} {} {} 255 0 0\n", p.0, p.1, p.2));
+        }
+        for e in self.edges.iter() {
+            out.push_str(&format!("{} {}\n", vertex[&e.parent], vertex[&e.child]));
+        }
+        out
+    }
+}
+
 pub fn manhattan(a: Pos, b: Pos) -> i32 {
     (a.0 - b.0).abs() + (a.1 - b.1).abs() + (a.2 - b.2).abs()
 }
@@ -525,4 +969,175 @@ mod tests {
         let parsed = input_generator(inp);
         assert_eq!(solve_part2(&parsed), 3621);
     }
+    #[test]
+    fn test_unmatched() {
+        let base: Vec<Pos> = (0..12)
+            .map(|i| (i * i, 3 * i - 7, 50 - i * i * i))
+            .collect();
+        // Scanner 1 sees the same beacons from (100, -20, 5), rotated a quarter turn about z
+        let seen: Vec<Pos> = base
+            .iter()
+            .map(|p| (p.1 + 20, -(p.0 - 100), p.2 - 5))
+            .collect();
+        let far: Vec<Pos> = (0..12).map(|i| (5000 + 7 * i, i * i, -i)).collect();
+
+        let (scanners, points) = solve_scanners(&[base.clone(), seen.clone()], 12).unwrap();
+        assert_eq!(points.len(), 12);
+        assert_eq!(scanners[1].position, (100, -20, 5));
+        assert_eq!(
+            solve_scanners(&[base.clone(), seen.clone()], 13),
+            Err(AlignmentError { unmatched: vec![1] })
+        );
+        let err = solve_scanners(&[base, far, seen], 12).unwrap_err();
+        assert_eq!(err.unmatched, vec![1]);
+        assert_eq!(err.to_string(), "could not align scanners [1]");
+    }
+    #[test]
+    fn test_rotation_group() {
+        let all = Rotation::all();
+        let set: HashSet<Rotation> = all.iter().copied().collect();
+        assert_eq!(set.len(), 24);
+        for a in all.iter() {
+            for b in all.iter() {
+                assert!(set.contains(&(*a * *b)));
+                let p = (1, -20, 300);
+                assert_eq!((*a * *b).apply(p), a.apply(b.apply(p)));
+            }
+            assert_eq!(*a * a.inverse(), Rotation::identity());
+            assert_eq!(a.inverse() * *a, Rotation::identity());
+            assert!(set.contains(&a.inverse()));
+        }
+    }
+    #[test]
+    fn test_rotation_orientation() {
+        for o in Orientation::iter() {
+            let r = Rotation::from(o);
+            assert_eq!(Orientation::from(r), o);
+            assert_eq!(Rotation::from_matrix(r.matrix()), Some(r));
+            assert_eq!(r.apply((4, -5, 6)), o.apply((4, -5, 6)));
+        }
+        assert_eq!(Rotation::from(Orientation::Nothing), Rotation::identity());
+        // A reflection is a signed permutation but not a rotation
+        assert_eq!(
+            Rotation::from_matrix([[-1, 0, 0], [0, 1, 0], [0, 0, 1]]),
+            None
+        );
+        assert_eq!(
+            Rotation::from_matrix([[1, 1, 0], [0, 1, 0], [0, 0, 1]]),
+            None
+        );
+    }
+    #[test]
+    fn test_map_scanners() {
+        let a: Vec<Pos> = (0..12)
+            .map(|i| (i * i, 3 * i - 7, 50 - i * i * i))
+            .collect();
+        let b: Vec<Pos> = (0..12)
+            .map(|i| (-400 - 5 * i, 2 * i * i + 9, 11 * i))
+            .collect();
+        // Scanner 1 sees everything from (100, -20, 5), rotated a quarter turn about z
+        let seen: Vec<Pos> = a
+            .iter()
+            .chain(b.iter())
+            .map(|p| (p.1 + 20, -(p.0 - 100), p.2 - 5))
+            .collect();
+        // Scanner 2 only sees `b`, from (-300, 40, 2), turned upside down about x
+        let only_b: Vec<Pos> = b
+            .iter()
+            .map(|p| (p.0 + 300, -(p.1 - 40), -(p.2 - 2)))
+            .collect();
+
+        let map = map_scanners(&[a, seen, only_b], 12).unwrap();
+        assert_eq!(map.beacons.len(), 24);
+        let positions: Vec<Pos> = map.scanners.iter().map(|s| s.position()).collect();
+        assert_eq!(positions, vec![(0, 0, 0), (100, -20, 5), (-300, 40, 2)]);
+        assert_eq!(map.scanners[2].parent(), Some(1));
+
+        let parents: Vec<(usize, usize)> = map.edges.iter().map(|e| (e.parent, e.child)).collect();
+        assert_eq!(parents, vec![(0, 1), (1, 2)]);
+        for e in map.edges.iter() {
+            let (p, c) = (&map.scanners[e.parent], &map.scanners[e.child]);
+            assert_eq!(p.rotation() * e.rotation, c.rotation());
+            let t = p.rotation().apply(e.translation);
+            let pos = (
+                p.position().0 + t.0,
+                p.position().1 + t.1,
+                p.position().2 + t.2,
+            );
+            assert_eq!(pos, c.position());
+        }
+
+        let xyz = map.to_xyz();
+        assert_eq!(xyz.lines().count(), 24);
+        assert_eq!(xyz.lines().next(), Some("-455 251 121"));
+        let ply = map.to_ply();
+        assert!(ply.starts_with("ply\nformat ascii 1.0\nelement vertex 27\n"));
+        assert!(ply.contains("element edge 2\n"));
+        assert!(ply.contains("\n100 -20 5 255 0 0\n"));
+        assert!(ply.ends_with("\n24 25\n25 26\n"));
+    }
+    #[test]
+    fn test_noisy() {
+        let mut seed: u64 = 19;
+        let mut jitter = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) % 3) as i32 - 1
+        };
+        let beacons: Vec<Pos> = (0..16)
+            .map(|i| {
+                (
+                    i * 97 % 800 - 400,
+                    i * i * 13 % 900 - 450,
+                    i * 311 % 700 - 350,
+                )
+            })
+            .collect();
+
+        // Scanner 0 misses the last beacon and sees one that isn't there
+        let mut s0: Vec<Pos> = beacons[..15]
+            .iter()
+            .map(|p| (p.0 + jitter(), p.1 + jitter(), p.2 + jitter()))
+            .collect();
+        s0.push((600, 600, 600));
+        // Scanner 1 is at (100, -20, 5), rotated a quarter turn about z, misses the first two
+        // beacons and sees two spurious ones
+        let mut s1: Vec<Pos> = beacons[2..]
+            .iter()
+            .map(|p| {
+                (
+                    p.1 + 20 + jitter(),
+                    -(p.0 - 100) + jitter(),
+                    p.2 - 5 + jitter(),
+                )
+            })
+            .collect();
+        s1.push((-700, 10, 10));
+        s1.push((30, 650, -600));
+        let input = vec![s0, s1];
+
+        assert!(solve_scanners(&input, 12).is_err());
+        let (scanners, merged) = solve_scanners_noisy(&input, 1, 12).unwrap();
+        assert!(chebyshev(scanners[1].position(), (100, -20, 5)) <= 1);
+        assert_eq!(
+            scanners[1].rotation().apply((1, 2, 3)),
+            (-2, 1, 3),
+            "quarter turn about z"
+        );
+        // 16 real beacons plus three spurious ones
+        assert_eq!(merged.len(), 19);
+
+        // Without jitter the noisy solver agrees with the exact one
+        let exact: Vec<Vec<Pos>> = vec![
+            beacons.clone(),
+            beacons
+                .iter()
+                .map(|p| (p.1 + 20, -(p.0 - 100), p.2 - 5))
+                .collect(),
+        ];
+        let (scanners, merged) = solve_scanners_noisy(&exact, 0, 12).unwrap();
+        assert_eq!(scanners[1].position(), (100, -20, 5));
+        assert_eq!(merged.len(), 16);
+    }
 }
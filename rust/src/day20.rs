@@ -1,4 +1,9 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use std::collections::HashMap;
+
+// ((top, left), (bottom, right))
+type Bounds = ((i64, i64), (i64, i64));
+type Grid = Vec<Vec<bool>>;
 
 #[aoc_generator(day20)]
 pub fn input_generator(input: &str) -> (Vec<bool>, Vec<Vec<bool>>) {
@@ -15,115 +20,353 @@ pub fn input_generator(input: &str) -> (Vec<bool>, Vec<Vec<bool>>) {
     (ie, grid)
 }
 
-#[aoc(day20, part1)]
-pub fn solve_part1(input: &(Vec<bool>, Vec<Vec<bool>>)) -> usize {
-    let mut base_image = input.1.clone();
-    for i in 0..2 {
-        let empty = input.0[0] && (i % 2 == 1);
-        // Enclose image with 1pixel border
-        dbg!(base_image.len(), base_image[0].len());
-        let mut new_image = Vec::new();
-        new_image.push(vec![empty; base_image[0].len() + 2]);
-        for line in base_image.iter() {
-            let mut l = Vec::with_capacity(line.len() + 2);
-            l.push(empty);
-            for x in line {
-                l.push(*x);
+// Image on an infinite canvas: the stored rectangle of bitset rows starts at (top, left) and
+// every pixel outside it has the background value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfiniteImage {
+    algorithm: Vec<bool>,
+    rows: Vec<Vec<u64>>,
+    width: usize,
+    top: i64,
+    left: i64,
+    background: bool,
+}
+
+impl InfiniteImage {
+    pub fn new(algorithm: &[bool], grid: &[Vec<bool>]) -> Self {
+        let width = grid.first().map(|l| l.len()).unwrap_or(0);
+        let mut out = InfiniteImage {
+            algorithm: algorithm.to_vec(),
+            rows: vec![vec![0; width.div_ceil(64)]; grid.len()],
+            width,
+            top: 0,
+            left: 0,
+            background: false,
+        };
+        for (y, l) in grid.iter().enumerate() {
+            for (x, v) in l.iter().enumerate() {
+                out.set(y, x, *v);
             }
-            l.push(empty);
-            new_image.push(l);
         }
-        new_image.push(vec![empty; base_image[0].len() + 2]);
-
-        base_image = new_image
-            .iter()
-            .enumerate()
-            .map(|l| {
-                l.1.iter()
-                    .enumerate()
-                    .map(|x| get_val(&new_image, (l.0, x.0), &input.0, empty))
-                    .collect()
+        out
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    // Position of the top-left stored pixel, relative to the original image
+    pub fn origin(&self) -> (i64, i64) {
+        (self.top, self.left)
+    }
+
+    pub fn background(&self) -> bool {
+        self.background
+    }
+
+    fn set(&mut self, y: usize, x: usize, v: bool) {
+        if v {
+            self.rows[y][x / 64] |= 1 << (x % 64);
+        } else {
+            self.rows[y][x / 64] &= !(1 << (x % 64));
+        }
+    }
+
+    // Pixel at (y, x) relative to the original image
+    pub fn get(&self, y: i64, x: i64) -> bool {
+        let (sy, sx) = (y - self.top, x - self.left);
+        if sy < 0 || sx < 0 || sy as usize >= self.height() || sx as usize >= self.width {
+            return self.background;
+        }
+        let (sy, sx) = (sy as usize, sx as usize);
+        (self.rows[sy][sx / 64] >> (sx % 64)) & 1 == 1
+    }
+
+    pub fn index(&self, y: i64, x: i64) -> usize {
+        let mut out = 0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                out = (out << 1) | self.get(y + dy, x + dx) as usize;
+            }
+        }
+        out
+    }
+
+    // Every pass can light the pixels just outside the stored rectangle, so it grows by one
+    // on each side. The background flips to ie[0] or ie[511] depending on its current value.
+    fn grown(&self) -> Self {
+        let width = self.width + 2;
+        InfiniteImage {
+            algorithm: self.algorithm.clone(),
+            rows: vec![vec![0; width.div_ceil(64)]; self.height() + 2],
+            width,
+            top: self.top - 1,
+            left: self.left - 1,
+            background: self.algorithm[if self.background { 511 } else { 0 }],
+        }
+    }
+
+    // Looks up all nine neighbours of every pixel, kept to check enhance_once against
+    pub fn enhance_once_naive(&self) -> Self {
+        let mut out = self.grown();
+        for y in 0..out.height() {
+            for x in 0..out.width {
+                let v = self.algorithm[self.index(out.top + y as i64, out.left + x as i64)];
+                out.set(y, x, v);
+            }
+        }
+        out
+    }
+
+    // Stored row r (or background outside the rectangle) shifted so bit j is the pixel at
+    // x = j - 2, meaning every window of the grown image lies within the words
+    fn padded_row(&self, r: i64) -> Vec<u64> {
+        let words = (self.width + 4).div_ceil(64);
+        if r < 0 || r as usize >= self.height() {
+            return vec![if self.background { u64::MAX } else { 0 }; words];
+        }
+        let row = &self.rows[r as usize];
+        let mut out: Vec<u64> = (0..words)
+            .map(|i| {
+                let cur = row.get(i).copied().unwrap_or(0);
+                let prev = if i > 0 { row[i - 1] } else { 0 };
+                (cur << 2) | (prev >> 62)
             })
             .collect();
-        // dbg!(&base_image);
+        if self.background {
+            out[0] |= 0b11;
+            for j in self.width + 2..words * 64 {
+                out[j / 64] |= 1 << (j % 64);
+            }
+        }
+        out
     }
-    base_image
-        .iter()
-        .map(|l| l.iter().filter(|x| **x).count())
-        .sum()
-}
 
-#[aoc(day20, part2)]
-pub fn solve_part2(input: &(Vec<bool>, Vec<Vec<bool>>)) -> usize {
-    let mut base_image = input.1.clone();
-    for i in 0..50 {
-        let empty = input.0[0] && (i % 2 == 1);
-        // Enclose image with 1pixel border
-        dbg!(base_image.len(), base_image[0].len());
-        let mut new_image = Vec::new();
-        new_image.push(vec![empty; base_image[0].len() + 2]);
-        for line in base_image.iter() {
-            let mut l = Vec::with_capacity(line.len() + 2);
-            l.push(empty);
-            for x in line {
-                l.push(*x);
+    // Works a word of 64 output pixels at a time: each of the three source rows is loaded as
+    // a 128-bit window, and shifting them by one bit per pixel gives the 9-bit index directly
+    pub fn enhance_once(&self) -> Self {
+        let mut out = self.grown();
+        let padded: Vec<Vec<u64>> = (-2..self.height() as i64 + 2)
+            .map(|r| self.padded_row(r))
+            .collect();
+        // Window bits come out least significant first, the reverse of each row's 3 bits in
+        // the index, so look up through a table with the rows reversed
+        let mut table = [0u64; 8];
+        for (i, t) in table.iter_mut().enumerate() {
+            for bit in 0..64 {
+                let idx = i * 64 + bit;
+                let rev = |r: usize| {
+                    let v = (idx >> (3 * r)) & 7;
+                    ((v & 1) << 2) | (v & 2) | (v >> 2)
+                };
+                if self.algorithm[(rev(2) << 6) | (rev(1) << 3) | rev(0)] {
+                    *t |= 1 << bit;
+                }
+            }
+        }
+        let fill = if self.background { u64::MAX } else { 0 };
+        let window = |row: &[u64], w: usize| {
+            row[w] as u128 | ((row.get(w + 1).copied().unwrap_or(fill) as u128) << 64)
+        };
+        let words = out.width.div_ceil(64);
+        for y in 0..out.height() {
+            for w in 0..words {
+                let top = window(&padded[y], w);
+                let mid = window(&padded[y + 1], w);
+                let bot = window(&padded[y + 2], w);
+                let mut word = 0;
+                // Halves of 32 pixels only need 34 bits of each window, so fit in a u64
+                for half in [0, 32] {
+                    let (t, m, b) = (
+                        (top >> half) as u64,
+                        (mid >> half) as u64,
+                        (bot >> half) as u64,
+                    );
+                    for k in 0..32 {
+                        let idx = ((((t >> k) & 7) << 6) | (((m >> k) & 7) << 3) | ((b >> k) & 7))
+                            as usize;
+                        word |= ((table[idx >> 6] >> (idx & 63)) & 1) << (half + k);
+                    }
+                }
+                out.rows[y][w] = word;
             }
-            l.push(empty);
-            new_image.push(l);
+            // Keep bits past the right edge clear
+            let tail = out.width % 64;
+            if tail != 0 {
+                out.rows[y][words - 1] &= (1 << tail) - 1;
+            }
+        }
+        out
+    }
+
+    pub fn enhance(&self, n: usize) -> Self {
+        (0..n).fold(self.clone(), |img, _| img.enhance_once())
+    }
+
+    // None when the background is lit, as infinitely many pixels are then lit
+    pub fn lit(&self) -> Option<usize> {
+        if self.background {
+            None
+        } else {
+            Some(
+                self.rows
+                    .iter()
+                    .flatten()
+                    .map(|w| w.count_ones() as usize)
+                    .sum(),
+            )
         }
-        new_image.push(vec![empty; base_image[0].len() + 2]);
-
-        base_image = new_image
-            .iter()
-            .enumerate()
-            .map(|l| {
-                l.1.iter()
-                    .enumerate()
-                    .map(|x| get_val(&new_image, (l.0, x.0), &input.0, empty))
+    }
+
+    pub fn to_grid(&self) -> Vec<Vec<bool>> {
+        (0..self.height() as i64)
+            .map(|y| {
+                (0..self.width as i64)
+                    .map(|x| self.get(self.top + y, self.left + x))
                     .collect()
             })
-            .collect();
-        // dbg!(&base_image);
+            .collect()
+    }
+
+    // Smallest rectangle holding every pixel that differs from the background, relative to
+    // the original image
+    pub fn bounds(&self) -> Option<Bounds> {
+        let mut out: Option<Bounds> = None;
+        for (y, row) in self.to_grid().iter().enumerate() {
+            for (x, v) in row.iter().enumerate() {
+                if *v != self.background {
+                    let (y, x) = (self.top + y as i64, self.left + x as i64);
+                    out = Some(match out {
+                        None => ((y, x), (y, x)),
+                        Some(((t, l), (b, r))) => ((t.min(y), l.min(x)), (b.max(y), r.max(x))),
+                    });
+                }
+            }
+        }
+        out
+    }
+
+    // Whether the pixels differing from the background provably spread without limit.
+    // Take the lit pixel P furthest right, and lowest among those: the pixel diagonally below
+    // right of it sees only P in its window, at index 256. If that lights it, the rightmost
+    // column moves right every pass. The other corners give the other three directions, and
+    // spreading both ways along an axis means the image keeps growing.
+    // Only applies while the background stays the same each pass.
+    pub fn grows_forever(&self) -> bool {
+        let bg = self.background;
+        if self.algorithm[if bg { 511 } else { 0 }] != bg || self.bounds().is_none() {
+            return false;
+        }
+        // A window holding only the background and one differing pixel at the given bit
+        let spreads = |bit: usize| self.algorithm[if bg { 511 ^ bit } else { bit }] != bg;
+        let (up_left, up_right, down_left, down_right) =
+            (spreads(1), spreads(4), spreads(64), spreads(256));
+        let right = down_right || up_right;
+        let left = down_left || up_left;
+        let down = down_right || down_left;
+        let up = up_right || up_left;
+        (right && left) || (down && up)
+    }
+
+    // Same image with the stored rectangle cut down to its bounds
+    pub fn trim(&self) -> Self {
+        let (grid, (top, left)) = match self.bounds() {
+            Some(((t, l), (b, r))) => (
+                (t..=b)
+                    .map(|y| (l..=r).map(|x| self.get(y, x)).collect())
+                    .collect(),
+                (t, l),
+            ),
+            None => (vec![], (0, 0)),
+        };
+        let mut out = InfiniteImage::new(&self.algorithm, &grid);
+        out.top = top;
+        out.left = left;
+        out.background = self.background;
+        out
     }
-    base_image
-        .iter()
-        .map(|l| l.iter().filter(|x| **x).count())
-        .sum()
 }
 
-pub fn get_val(grid: &Vec<Vec<bool>>, pos: (usize, usize), ie: &[bool], empty: bool) -> bool {
-    ie[get_index(grid, pos, empty)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Evolution {
+    // The image after `start` passes reappears every `period` passes, moved by `shift`
+    Periodic {
+        start: usize,
+        period: usize,
+        shift: (i64, i64),
+    },
+    // Provably keeps growing, see InfiniteImage::grows_forever
+    Unbounded,
+    // No repeat within `passes` passes, and no proof of growth
+    Inconclusive {
+        passes: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvolutionReport {
+    pub evolution: Evolution,
+    // Lit pixels and bounds before any passes and after each pass
+    pub lit: Vec<Option<usize>>,
+    pub bounds: Vec<Option<Bounds>>,
+}
+
+// Enhances until the trimmed image and background repeat (in any position) or max_passes
+// is reached, then checks whether the image provably grows
+pub fn evolve(img: &InfiniteImage, max_passes: usize) -> EvolutionReport {
+    // Keyed on background and trimmed pixels, remembering the pass and position
+    let mut seen: HashMap<(bool, Grid), (usize, (i64, i64))> = HashMap::new();
+    let mut lit = Vec::new();
+    let mut bounds = Vec::new();
+    let mut img = img.trim();
+    for pass in 0..=max_passes {
+        lit.push(img.lit());
+        bounds.push(img.bounds());
+        let key = (img.background(), img.to_grid());
+        if let Some((start, origin)) = seen.get(&key) {
+            return EvolutionReport {
+                evolution: Evolution::Periodic {
+                    start: *start,
+                    period: pass - start,
+                    shift: (img.top - origin.0, img.left - origin.1),
+                },
+                lit,
+                bounds,
+            };
+        }
+        seen.insert(key, (pass, img.origin()));
+        img = img.enhance_once().trim();
+    }
+    let evolution = if img.grows_forever() {
+        Evolution::Unbounded
+    } else {
+        Evolution::Inconclusive { passes: max_passes }
+    };
+    EvolutionReport {
+        evolution,
+        lit,
+        bounds,
+    }
 }
 
-pub fn get_index(grid: &Vec<Vec<bool>>, pos: (usize, usize), empty: bool) -> usize {
-    let (y, x) = pos;
-    let mut out: usize = 0;
-    // dbg!(&grid);
-    let bin = [
-        y.checked_sub(1)
-            .map(|ym| x.checked_sub(1).map(|xm| grid[ym][xm]).unwrap_or(empty))
-            .unwrap_or(empty),
-        y.checked_sub(1).map(|ym| grid[ym][x]).unwrap_or(empty),
-        y.checked_sub(1)
-            .map(|ym| (grid[ym].get(x + 1).map(|z| *z)).unwrap_or(empty))
-            .unwrap_or(empty),
-        x.checked_sub(1).map(|xm| grid[y][xm]).unwrap_or(empty),
-        grid[y][x],
-        (grid[y].get(x + 1).map(|z| *z)).unwrap_or(empty),
-        grid.get(y + 1)
-            .map(|yp| x.checked_sub(1).map(|xm| yp[xm]).unwrap_or(empty))
-            .unwrap_or(empty),
-        grid.get(y + 1).map(|yp| yp[x]).unwrap_or(empty),
-        grid.get(y + 1)
-            .map(|yp| yp.get(x + 1).map(|z| *z).unwrap_or(empty))
-            .unwrap_or(empty),
-    ];
-    // dbg!(&bin);
-    for x in (0..bin.len()) {
-        out = out << 1;
-        out += (if bin[x] { 1 } else { 0 });
-    }
-    out
+#[aoc(day20, part1)]
+pub fn solve_part1(input: &(Vec<bool>, Vec<Vec<bool>>)) -> usize {
+    InfiniteImage::new(&input.0, &input.1)
+        .enhance(2)
+        .lit()
+        .unwrap()
+}
+
+#[aoc(day20, part2)]
+pub fn solve_part2(input: &(Vec<bool>, Vec<Vec<bool>>)) -> usize {
+    InfiniteImage::new(&input.0, &input.1)
+        .enhance(50)
+        .lit()
+        .unwrap()
 }
 
 #[cfg(test)]
@@ -140,7 +383,7 @@ mod tests {
 ..#..
 ..###";
         let parsed = input_generator(inp);
-        assert_eq!(get_index(&parsed.1, (2, 2), false), 34);
+        assert_eq!(InfiniteImage::new(&parsed.0, &parsed.1).index(2, 2), 34);
     }
     #[test]
     fn test_part1() {
@@ -166,4 +409,112 @@ mod tests {
         let parsed = input_generator(inp);
         assert_eq!(solve_part2(&parsed), 3351);
     }
+    #[test]
+    fn test_background() {
+        // Algorithm lights everything from a dark neighbourhood and darkens a fully lit one,
+        // so the background flips on every pass
+        let mut ie = vec![false; 512];
+        ie[0] = true;
+        let img = InfiniteImage::new(&ie, &[vec![true]]);
+        assert!(img.enhance(1).background());
+        assert_eq!(img.enhance(1).lit(), None);
+        assert!(!img.enhance(2).background());
+        // Only the centre sees the dark block left by the first pass
+        assert_eq!(img.enhance(2).lit(), Some(1));
+
+        // Once lit the background stays lit when ie[511] is set
+        ie[511] = true;
+        let img = InfiniteImage::new(&ie, &[vec![true]]);
+        assert!(img.enhance(3).background());
+        assert_eq!(img.enhance(3).origin(), (-3, -3));
+        assert_eq!(img.enhance(3).to_grid().len(), 7);
+    }
+    #[test]
+    fn test_kernel_matches_naive() {
+        let inp = "..#.#..#####.#.#.#.###.##.....###.##.#..###.####..#####..#....#..#..##..###..######.###...####..#..#####..##..#.#####...##.#.#..#.##..#.#......#.###.######.###.####...#.##.##..#..#..#####.....#.#....###..#.##......#.....#..#..#..##..#...##.######.####.####.#.#...#.......#..#.#.#...####.##.#......#..#...##.#.##..#...##.#.##..###.#......#.#.......#.#.#.####.###.##...#.....####.#..#..#.##.#....##..#.####....##...##..#...#......#.#.......#.......##..####..#...#.#.#...##..#.#..###..#####........#..####......#..#
+
+#..#.
+#....
+##..#
+..#..
+..###";
+        let (ie, grid) = input_generator(inp);
+        // Flipping ie[0] makes the background alternate, exercising the padded edges
+        let mut flipping = ie.clone();
+        flipping[0] = true;
+        flipping[511] = false;
+        for algorithm in [ie, flipping] {
+            let mut fast = InfiniteImage::new(&algorithm, &grid);
+            let mut naive = fast.clone();
+            // Grow past a word boundary
+            for _ in 0..40 {
+                fast = fast.enhance_once();
+                naive = naive.enhance_once_naive();
+                assert_eq!(fast, naive);
+            }
+        }
+    }
+    #[test]
+    fn test_evolve() {
+        // Every pixel copies its upper-left neighbour, so a single pixel glides diagonally
+        let ie: Vec<bool> = (0..512).map(|i| i & 256 != 0).collect();
+        let report = evolve(&InfiniteImage::new(&ie, &[vec![true]]), 10);
+        assert_eq!(
+            report.evolution,
+            Evolution::Periodic {
+                start: 0,
+                period: 1,
+                shift: (1, 1)
+            }
+        );
+        assert_eq!(report.lit, vec![Some(1), Some(1)]);
+        assert_eq!(report.bounds[1], Some(((1, 1), (1, 1))));
+
+        // A lit background with ie[511] unset and ie[0] set blinks with period 2
+        let mut ie = vec![false; 512];
+        ie[0] = true;
+        let report = evolve(&InfiniteImage::new(&ie, &[vec![false]]), 10);
+        assert_eq!(
+            report.evolution,
+            Evolution::Periodic {
+                start: 0,
+                period: 2,
+                shift: (0, 0)
+            }
+        );
+        assert_eq!(report.lit, vec![Some(0), None, Some(0)]);
+
+        // Lighting any pixel next to a lit one grows forever
+        let ie: Vec<bool> = (0..512).map(|i| i != 0).collect();
+        let report = evolve(&InfiniteImage::new(&ie, &[vec![true]]), 5);
+        assert_eq!(report.evolution, Evolution::Unbounded);
+        assert_eq!(
+            report.lit,
+            vec![Some(1), Some(9), Some(25), Some(49), Some(81), Some(121)]
+        );
+
+        // Periods longer than the pass limit aren't mistaken for growth
+        let mut ie = vec![false; 512];
+        ie[0] = true;
+        let report = evolve(&InfiniteImage::new(&ie, &[vec![false]]), 1);
+        assert_eq!(report.evolution, Evolution::Inconclusive { passes: 1 });
+        // The glider only spreads one way in each axis, so it's moving rather than growing
+        let ie: Vec<bool> = (0..512).map(|i| i & 256 != 0).collect();
+        let img = InfiniteImage::new(&ie, &[vec![true]]);
+        assert!(!img.grows_forever());
+        assert_eq!(
+            evolve(&img, 0).evolution,
+            Evolution::Inconclusive { passes: 0 }
+        );
+        // Lit on an unlit background spreading right and left, complemented onto a lit one
+        let mut ie = vec![false; 512];
+        ie[16] = true;
+        ie[256] = true;
+        ie[64] = true;
+        assert!(InfiniteImage::new(&ie, &[vec![true]]).grows_forever());
+        let inverted: Vec<bool> = (0..512).map(|i| !ie[511 - i]).collect();
+        let mut img = InfiniteImage::new(&inverted, &[vec![true, false, true]]);
+        img.background = true;
+        assert!(img.grows_forever());
+    }
 }
@@ -1,6 +1,7 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use regex::Regex;
-use std::ops::RangeInclusive;
+use std::collections::{BTreeMap, HashMap};
+
 #[aoc_generator(day21)]
 pub fn input_generator(input: &str) -> (u8, u8) {
     let re1 = Regex::new("Player 1 starting position: ([0-9]+)").unwrap();
@@ -27,180 +28,409 @@ pub fn input_generator(input: &str) -> (u8, u8) {
     )
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rules {
+    pub die_faces: usize,
+    pub rolls_per_turn: usize,
+    pub board_size: usize,
+    pub target: usize,
+}
+
+impl Rules {
+    pub fn part1() -> Rules {
+        Rules {
+            die_faces: 100,
+            rolls_per_turn: 3,
+            board_size: 10,
+            target: 1000,
+        }
+    }
+
+    pub fn part2() -> Rules {
+        Rules {
+            die_faces: 3,
+            rolls_per_turn: 3,
+            board_size: 10,
+            target: 21,
+        }
+    }
+
+    // Spaces are numbered 1..=board_size and wrap around
+    pub fn advance(&self, pos: usize, roll: usize) -> usize {
+        (pos + roll - 1) % self.board_size + 1
+    }
+
+    // Number of universes producing each total for one turn of rolls
+    pub fn roll_counts(&self) -> Vec<(usize, usize)> {
+        turn_counts(&mut QuantumDie::new(self.die_faces), self.rolls_per_turn)
+    }
+}
+
+pub trait Die {
+    // Every value the next roll can take, with the number of universes it happens in
+    fn outcomes(&mut self) -> Vec<(usize, usize)>;
+}
+
+// Rolls 1, 2, .., faces, 1, ..
+#[derive(Debug, Clone)]
+pub struct DeterministicDie {
+    pub faces: usize,
+    next: usize,
+}
+
+impl DeterministicDie {
+    pub fn new(faces: usize) -> Self {
+        DeterministicDie { faces, next: 1 }
+    }
+}
+
+impl Die for DeterministicDie {
+    fn outcomes(&mut self) -> Vec<(usize, usize)> {
+        let out = self.next;
+        self.next = self.next % self.faces + 1;
+        vec![(out, 1)]
+    }
+}
+
+// Pseudo-random rolls from a xorshift64* generator, repeatable for a given seed
+#[derive(Debug, Clone)]
+pub struct SeededDie {
+    pub faces: usize,
+    state: u64,
+}
+
+impl SeededDie {
+    pub fn new(faces: usize, seed: u64) -> Self {
+        // xorshift gets stuck at 0
+        SeededDie {
+            faces,
+            state: seed.max(1),
+        }
+    }
+}
+
+impl Die for SeededDie {
+    fn outcomes(&mut self) -> Vec<(usize, usize)> {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let x = self.state.wrapping_mul(0x2545F4914F6CDD1D);
+        vec![((x >> 32) as usize % self.faces + 1, 1)]
+    }
+}
+
+// Dirac die: every roll splits into one universe per face
+#[derive(Debug, Clone)]
+pub struct QuantumDie {
+    pub faces: usize,
+}
+
+impl QuantumDie {
+    pub fn new(faces: usize) -> Self {
+        QuantumDie { faces }
+    }
+}
+
+impl Die for QuantumDie {
+    fn outcomes(&mut self) -> Vec<(usize, usize)> {
+        (1..=self.faces).map(|f| (f, 1)).collect()
+    }
+}
+
+// Number of universes producing each total over a turn of rolls
+pub fn turn_counts(die: &mut impl Die, rolls_per_turn: usize) -> Vec<(usize, usize)> {
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    counts.insert(0, 1);
+    for _ in 0..rolls_per_turn {
+        let outcomes = die.outcomes();
+        let mut next = HashMap::new();
+        for (total, n) in counts.iter() {
+            for (roll, m) in outcomes.iter() {
+                *next.entry(total + roll).or_insert(0) += n * m;
+            }
+        }
+        counts = next;
+    }
+    let mut out: Vec<(usize, usize)> = counts.into_iter().collect();
+    out.sort_unstable();
+    out
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turn {
+    pub player: usize,
+    pub rolls: Vec<usize>,
+    pub position: usize,
+    pub score: usize,
+}
+
+// Plays a single universe until someone reaches the target, so every roll must have
+// exactly one outcome
+pub fn play(rules: &Rules, starts: &[usize], die: &mut impl Die) -> Vec<Turn> {
+    let mut positions = starts.to_vec();
+    let mut scores = vec![0; starts.len()];
+    let mut turns = Vec::new();
+    for player in (0..starts.len()).cycle() {
+        let rolls: Vec<usize> = (0..rules.rolls_per_turn)
+            .map(|_| match die.outcomes()[..] {
+                [(roll, _)] => roll,
+                _ => panic!("die must have a single outcome per roll"),
+            })
+            .collect();
+        positions[player] = rules.advance(positions[player], rolls.iter().sum());
+        scores[player] += positions[player];
+        turns.push(Turn {
+            player,
+            rolls,
+            position: positions[player],
+            score: scores[player],
+        });
+        if scores[player] >= rules.target {
+            break;
+        }
+    }
+    turns
+}
+
+// Scores after the last turn each player took
+pub fn final_scores(turns: &[Turn], players: usize) -> Vec<usize> {
+    let mut scores = vec![0; players];
+    turns.iter().for_each(|t| scores[t.player] = t.score);
+    scores
+}
+
+// Matches the puzzle's description of each turn
+pub fn format_trace(turns: &[Turn]) -> String {
+    turns
+        .iter()
+        .map(|t| {
+            format!(
+                "Player {} rolls {} and moves to space {} for a total score of {}.",
+                t.player + 1,
+                t.rolls
+                    .iter()
+                    .map(|r| r.to_string())
+                    .collect::<Vec<String>>()
+                    .join("+"),
+                t.position,
+                t.score
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 #[aoc(day21, part1)]
 pub fn solve_part1(input: &(u8, u8)) -> usize {
-    let mut p1score: usize = 0;
-    let mut p2score: usize = 0;
-    let mut num_rolls = 0;
-    let mut i = 0;
-
-    let mut s1 = input.0;
-    let mut s2 = input.1;
-    let out = loop {
-        // (i+1, i+2, i+3) = 3i+6
-        //
-        let mut place = if i % 2 == 0 {
-            let p = (s1 as usize + (9 * i + 6)) % 10;
-            s1 = p as u8;
-            p
-        } else {
-            let p = (s2 as usize + (9 * i + 6)) % 10;
-            s2 = p as u8;
-            p
-        };
-        num_rolls += 3;
-        if place == 0 {
-            place = 10;
+    let rules = Rules::part1();
+    let turns = play(
+        &rules,
+        &[input.0 as usize, input.1 as usize],
+        &mut DeterministicDie::new(rules.die_faces),
+    );
+    final_scores(&turns, 2).iter().min().unwrap() * turns.len() * rules.rolls_per_turn
+}
+
+// Number of universes in which each player wins, with players moving in order
+pub fn quantum_wins(rules: &Rules, starts: &[usize]) -> Vec<usize> {
+    let mut memo = HashMap::new();
+    wins_from(
+        rules,
+        &rules.roll_counts(),
+        starts.to_vec(),
+        vec![0; starts.len()],
+        &mut memo,
+    )
+}
+
+// Win counts with the player to move first in positions/scores (later players follow in order)
+pub fn wins_from(
+    rules: &Rules,
+    roll_counts: &[(usize, usize)],
+    positions: Vec<usize>,
+    scores: Vec<usize>,
+    memo: &mut HashMap<(Vec<usize>, Vec<usize>), Vec<usize>>,
+) -> Vec<usize> {
+    if let Some(wins) = memo.get(&(positions.clone(), scores.clone())) {
+        return wins.clone();
+    }
+    let players = positions.len();
+    let mut wins = vec![0; players];
+    for &(roll, n) in roll_counts {
+        let pos = rules.advance(positions[0], roll);
+        let score = scores[0] + pos;
+        if score >= rules.target {
+            wins[0] += n;
+            continue;
         }
-        if i % 2 == 0 {
-            p1score += place as usize;
-            if p1score >= 1000 {
-                // println!(
-                //     "p1 wins score: {}, p2 score: {}, num_rolls: {}",
-                //     p1score, p2score, num_rolls
-                // );
-                break p2score * num_rolls;
-            }
-        } else {
-            // println!("i: {}, before p2score: {}, adding: {}", i, p2score, place);
-            p2score += place as usize;
-            if p2score >= 1000 {
-                // println!(
-                //     "p2 wins score: {}, p1 score: {}, num_rolls: {}",
-                //     p2score, p1score, num_rolls
-                // );
-                break p1score * num_rolls;
-            }
+        // Rotate so the next player moves first
+        let mut next_positions = positions.clone();
+        let mut next_scores = scores.clone();
+        next_positions[0] = pos;
+        next_scores[0] = score;
+        next_positions.rotate_left(1);
+        next_scores.rotate_left(1);
+        let sub = wins_from(rules, roll_counts, next_positions, next_scores, memo);
+        for (i, w) in sub.iter().enumerate() {
+            wins[(i + 1) % players] += n * w;
         }
+    }
+    memo.insert((positions, scores), wins.clone());
+    wins
+}
 
-        i += 1;
-    };
+// Universes aren't equally likely: one finishing after t turns has probability
+// faces^-(rolls_per_turn * t). The counts match the puzzle, the probabilities weight each
+// universe by the chance of its rolls.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+    pub wins: Vec<usize>,
+    // Number of universes finishing after each number of turns
+    pub lengths: BTreeMap<usize, usize>,
+    // Number of universes finishing with each set of final scores
+    pub final_scores: BTreeMap<Vec<usize>, usize>,
+    pub win_probabilities: Vec<f64>,
+    pub length_probabilities: BTreeMap<usize, f64>,
+    pub final_score_probabilities: BTreeMap<Vec<usize>, f64>,
+}
 
-    out
+impl Distribution {
+    pub fn universes(&self) -> usize {
+        self.lengths.values().sum()
+    }
+
+    // Expected number of turns
+    pub fn expected_length(&self) -> f64 {
+        self.length_probabilities
+            .iter()
+            .map(|(turns, p)| *turns as f64 * p)
+            .sum()
+    }
+
+    pub fn expected_scores(&self) -> Vec<f64> {
+        let mut out = vec![0.0; self.wins.len()];
+        for (scores, p) in self.final_score_probabilities.iter() {
+            for (o, s) in out.iter_mut().zip(scores.iter()) {
+                *o += *s as f64 * p;
+            }
+        }
+        out
+    }
 }
 
-pub struct State {
-    pub p1score: u8,
-    pub p2score: u8,
-    pub i: u8,
-    pub s1: u8,
-    pub s2: u8,
-    pub p1multi: usize,
-    pub p2multi: usize,
-}
-
-pub fn multiplier(x: u8) -> usize {
-    match x {
-        3 => 1,
-        4 => 3,
-        5 => 6,
-        6 => 7,
-        7 => 6,
-        8 => 3,
-        9 => 1,
-        _ => panic!("Bad number"),
-    }
-}
-
-pub fn step(state: State) -> (Vec<State>, usize, usize) {
-    let mut pending: Vec<State> = Vec::new();
-    let mut p1wins: usize = 0;
-    let mut p2wins: usize = 0;
-    let range: RangeInclusive<u8> = (3..=9);
-    range
-        .map(|r| {
-            if state.i % 2 == 0 {
-                let s1 = (state.s1 + r) % 10;
-                let p1score = state.p1score + if (s1 == 0) { 10 } else { s1 };
-                let p1multi = state.p1multi * multiplier(r);
-                let p2multi = state.p2multi * multiplier(r);
-                if p1score >= 21 {
-                    (None, p1multi, 0)
-                } else {
-                    (
-                        Some(State {
-                            p1score,
-                            p2score: state.p2score,
-                            i: state.i + 1,
-                            s1,
-                            s2: state.s2,
-                            p1multi,
-                            p2multi,
-                        }),
-                        0,
-                        0,
-                    )
-                }
-            } else {
-                let s2 = (state.s2 + r) % 10;
-                let p2score = state.p2score + if (s2 == 0) { 10 } else { s2 };
-                let p1multi = state.p1multi * multiplier(r);
-                let p2multi = state.p2multi * multiplier(r);
-                if p2score >= 21 {
-                    (None, 0, p2multi)
+// Expands all universes one turn at a time, merging those in the same (positions, scores)
+pub fn outcome_distribution(rules: &Rules, starts: &[usize]) -> Distribution {
+    let players = starts.len();
+    let roll_counts = rules.roll_counts();
+    let per_turn = (rules.die_faces as f64).powi(rules.rolls_per_turn as i32);
+    let mut out = Distribution {
+        wins: vec![0; players],
+        lengths: BTreeMap::new(),
+        final_scores: BTreeMap::new(),
+        win_probabilities: vec![0.0; players],
+        length_probabilities: BTreeMap::new(),
+        final_score_probabilities: BTreeMap::new(),
+    };
+    let mut pending: HashMap<(Vec<usize>, Vec<usize>), usize> = HashMap::new();
+    pending.insert((starts.to_vec(), vec![0; players]), 1);
+
+    let mut turn = 0;
+    while !pending.is_empty() {
+        let player = turn % players;
+        // Every universe still pending has made turn + 1 turns of rolls once this one is done
+        let weight = per_turn.powi(-(turn as i32 + 1));
+        let mut next = HashMap::new();
+        for ((positions, scores), n) in pending.into_iter() {
+            for &(roll, m) in roll_counts.iter() {
+                let mut positions = positions.clone();
+                let mut scores = scores.clone();
+                positions[player] = rules.advance(positions[player], roll);
+                scores[player] += positions[player];
+                if scores[player] >= rules.target {
+                    let p = (n * m) as f64 * weight;
+                    out.wins[player] += n * m;
+                    out.win_probabilities[player] += p;
+                    *out.lengths.entry(turn + 1).or_insert(0) += n * m;
+                    *out.length_probabilities.entry(turn + 1).or_insert(0.0) += p;
+                    *out.final_score_probabilities
+                        .entry(scores.clone())
+                        .or_insert(0.0) += p;
+                    *out.final_scores.entry(scores).or_insert(0) += n * m;
                 } else {
-                    (
-                        Some(State {
-                            p1score: state.p1score,
-                            p2score,
-                            i: state.i + 1,
-                            s1: state.s1,
-                            s2,
-                            p1multi,
-                            p2multi,
-                        }),
-                        0,
-                        0,
-                    )
+                    *next.entry((positions, scores)).or_insert(0) += n * m;
                 }
             }
-        })
-        .for_each(|x| match x {
-            (Some(s), _, _) => {
-                pending.push(s);
-            }
-            (None, x, y) => {
-                p1wins += x;
-                p2wins += y;
-            }
-        });
+        }
+        pending = next;
+        turn += 1;
+    }
+    out
+}
 
-    (pending, p1wins, p2wins)
+// Chance each player wins with the player to move first in positions/scores, like wins_from
+// but weighting each roll total by its probability rather than its number of universes
+pub fn win_chances_from(
+    rules: &Rules,
+    roll_chances: &[(usize, f64)],
+    positions: Vec<usize>,
+    scores: Vec<usize>,
+    memo: &mut HashMap<(Vec<usize>, Vec<usize>), Vec<f64>>,
+) -> Vec<f64> {
+    if let Some(chances) = memo.get(&(positions.clone(), scores.clone())) {
+        return chances.clone();
+    }
+    let players = positions.len();
+    let mut chances = vec![0.0; players];
+    for &(roll, p) in roll_chances {
+        let pos = rules.advance(positions[0], roll);
+        let score = scores[0] + pos;
+        if score >= rules.target {
+            chances[0] += p;
+            continue;
+        }
+        let mut next_positions = positions.clone();
+        let mut next_scores = scores.clone();
+        next_positions[0] = pos;
+        next_scores[0] = score;
+        next_positions.rotate_left(1);
+        next_scores.rotate_left(1);
+        let sub = win_chances_from(rules, roll_chances, next_positions, next_scores, memo);
+        for (i, c) in sub.iter().enumerate() {
+            chances[(i + 1) % players] += p * c;
+        }
+    }
+    memo.insert((positions, scores), chances.clone());
+    chances
+}
+
+// Probability that player 1 wins for each pair of starting spaces, indexed
+// [player 1 start - 1][player 2 start - 1]
+pub fn win_probabilities(rules: &Rules) -> Vec<Vec<f64>> {
+    let per_turn = (rules.die_faces as f64).powi(rules.rolls_per_turn as i32);
+    let roll_chances: Vec<(usize, f64)> = rules
+        .roll_counts()
+        .into_iter()
+        .map(|(roll, n)| (roll, n as f64 / per_turn))
+        .collect();
+    // Positions and scores reached are shared between starts, so reuse one memo
+    let mut memo = HashMap::new();
+    (1..=rules.board_size)
+        .map(|s1| {
+            (1..=rules.board_size)
+                .map(|s2| {
+                    win_chances_from(rules, &roll_chances, vec![s1, s2], vec![0, 0], &mut memo)[0]
+                })
+                .collect()
+        })
+        .collect()
 }
 
 #[aoc(day21, part2)]
 pub fn solve_part2(input: &(u8, u8)) -> usize {
-    let mut p1wins;
-    let mut p2wins;
-
-    let mut pending;
-    let mut i = 0;
-
-    let res = step(State {
-        p1score: 0,
-        p2score: 0,
-        i: 0,
-        s1: input.0,
-        s2: input.1,
-        p1multi: 1,
-        p2multi: 1,
-    });
-    pending = res.0;
-    p1wins = res.1;
-    p2wins = res.2;
-
-    while !pending.is_empty() {
-        let state = pending.pop().unwrap();
-        let mut res = step(state);
-        p1wins += res.1;
-        p2wins += res.2;
-        pending.append(&mut res.0);
-    }
-    if p1wins >= p2wins {
-        p1wins
-    } else {
-        p2wins
-    }
+    quantum_wins(&Rules::part2(), &[input.0 as usize, input.1 as usize])
+        .into_iter()
+        .max()
+        .unwrap()
 }
 
 #[cfg(test)]
@@ -227,4 +457,85 @@ Player 2 starting position: 8";
         let parsed = input_generator(inp);
         assert_eq!(solve_part2(&parsed), 444356092776315);
     }
+    #[test]
+    fn test_roll_counts() {
+        assert_eq!(
+            Rules::part2().roll_counts(),
+            vec![(3, 1), (4, 3), (5, 6), (6, 7), (7, 6), (8, 3), (9, 1)]
+        );
+    }
+    #[test]
+    fn test_deterministic() {
+        let turns = play(&Rules::part1(), &[4, 8], &mut DeterministicDie::new(100));
+        assert_eq!(final_scores(&turns, 2), vec![1000, 745]);
+        assert_eq!(turns.len() * 3, 993);
+        assert_eq!(
+            format_trace(&turns[..4]),
+            "Player 1 rolls 1+2+3 and moves to space 10 for a total score of 10.
+Player 2 rolls 4+5+6 and moves to space 3 for a total score of 3.
+Player 1 rolls 7+8+9 and moves to space 4 for a total score of 14.
+Player 2 rolls 10+11+12 and moves to space 6 for a total score of 9."
+        );
+    }
+    #[test]
+    fn test_seeded() {
+        let rules = Rules::part2();
+        let turns = play(&rules, &[4, 8], &mut SeededDie::new(3, 42));
+        assert_eq!(turns, play(&rules, &[4, 8], &mut SeededDie::new(3, 42)));
+        assert!(turns
+            .iter()
+            .flat_map(|t| t.rolls.iter())
+            .all(|r| (1..=3).contains(r)));
+        assert!(turns.last().unwrap().score >= 21);
+    }
+    #[test]
+    fn test_quantum_wins() {
+        assert_eq!(
+            quantum_wins(&Rules::part2(), &[4, 8]),
+            vec![444356092776315, 341960390180808]
+        );
+        // One roll of a 2-sided die to 3 points: the first player wins every universe
+        let rules = Rules {
+            die_faces: 2,
+            rolls_per_turn: 1,
+            board_size: 4,
+            target: 3,
+        };
+        assert_eq!(quantum_wins(&rules, &[2, 2, 2]), vec![2, 0, 0]);
+    }
+    #[test]
+    fn test_distribution() {
+        let dist = outcome_distribution(&Rules::part2(), &[4, 8]);
+        assert_eq!(dist.wins, quantum_wins(&Rules::part2(), &[4, 8]));
+        assert_eq!(dist.universes(), 444356092776315 + 341960390180808);
+        assert_eq!(dist.final_scores.values().sum::<usize>(), dist.universes());
+        // Player 1 scores at most 10 a turn so needs at least 3 turns
+        assert_eq!(*dist.lengths.keys().min().unwrap(), 5);
+
+        let total: f64 = dist.win_probabilities.iter().sum();
+        assert!((total - 1.0).abs() < 1e-12);
+        let total: f64 = dist.length_probabilities.values().sum();
+        assert!((total - 1.0).abs() < 1e-12);
+        // Short games have few universes but each is far more likely, so weighting by
+        // probability differs from the universe share
+        let share = dist.wins[0] as f64 / dist.universes() as f64;
+        assert!((dist.win_probabilities[0] - share).abs() > 0.01);
+        let length = dist.expected_length();
+        assert!(length > 5.0 && length < 12.0);
+        let scores = dist.expected_scores();
+        assert!(scores.iter().any(|s| *s >= 21.0));
+    }
+    #[test]
+    fn test_win_probabilities() {
+        let table = win_probabilities(&Rules::part2());
+        assert_eq!(table.len(), 10);
+        // Agrees with expanding every universe forward
+        let dist = outcome_distribution(&Rules::part2(), &[4, 8]);
+        assert!((table[3][7] - dist.win_probabilities[0]).abs() < 1e-12);
+        for (s1, s2) in [(1, 1), (10, 3), (6, 9)] {
+            let dist = outcome_distribution(&Rules::part2(), &[s1, s2]);
+            assert!((table[s1 - 1][s2 - 1] - dist.win_probabilities[0]).abs() < 1e-12);
+        }
+        assert!(table.iter().flatten().all(|p| (0.0..=1.0).contains(p)));
+    }
 }
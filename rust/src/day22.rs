@@ -1,197 +1,394 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use regex::Regex;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+
+// Axis-aligned box in N dimensions, as an inclusive (min, max) range per axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cuboid<const N: usize = 3>(pub [(i32, i32); N]);
+
+pub type Step<const N: usize = 3> = (bool, Cuboid<N>);
 
 #[aoc_generator(day22)]
-pub fn input_generator(input: &str) -> Vec<(bool, (i32, i32), (i32, i32), (i32, i32))> {
-    let re = Regex::new("(on|off) x=([0-9\\-]+)\\.\\.([0-9\\-]+),y=([0-9\\-]+)\\.\\.([0-9\\-]+),z=([0-9\\-]+)\\.\\.([0-9\\-]+)").unwrap();
+pub fn input_generator(input: &str) -> Vec<Step> {
+    parse_steps(input)
+}
+
+// Parses lines like "on x=10..12,y=10..12" with one range per axis, in order
+pub fn parse_steps<const N: usize>(input: &str) -> Vec<Step<N>> {
+    let re = Regex::new("[a-z]=([0-9\\-]+)\\.\\.([0-9\\-]+)").unwrap();
     input
         .lines()
         .map(|l| {
-            let c = re.captures(l).unwrap();
-            let b = c.get(1).unwrap().as_str() == "on";
-            (
-                b,
-                (
+            let b = if l.starts_with("on ") {
+                true
+            } else if l.starts_with("off ") {
+                false
+            } else {
+                panic!("step is neither on nor off: {:?}", l)
+            };
+            let mut ranges = [(0, 0); N];
+            let mut caps = re.captures_iter(l);
+            for r in ranges.iter_mut() {
+                let c = caps
+                    .next()
+                    .unwrap_or_else(|| panic!("missing axis: {:?}", l));
+                *r = (
+                    c.get(1).unwrap().as_str().parse().unwrap(),
                     c.get(2).unwrap().as_str().parse().unwrap(),
-                    c.get(3).unwrap().as_str().parse().unwrap(),
-                ),
-                (
-                    c.get(4).unwrap().as_str().parse().unwrap(),
-                    c.get(5).unwrap().as_str().parse().unwrap(),
-                ),
-                (
-                    c.get(6).unwrap().as_str().parse().unwrap(),
-                    c.get(7).unwrap().as_str().parse().unwrap(),
-                ),
-            )
+                );
+            }
+            assert!(caps.next().is_none(), "too many axes: {:?}", l);
+            (b, Cuboid(ranges))
         })
         .collect()
 }
 
 #[aoc(day22, part1)]
-pub fn solve_part1(input: &[(bool, (i32, i32), (i32, i32), (i32, i32))]) -> usize {
-    // let mut board: HashSet<(i32, i32, i32)> = HashSet::new();
-
-    // for i in input.iter().filter(|i| {
-    //     i.1 .0 >= -50
-    //         && i.1 .1 <= 50
-    //         && i.2 .0 >= -50
-    //         && i.2 .1 <= 50
-    //         && i.3 .0 >= -50
-    //         && i.3 .1 <= 50
-    // }) {
-    //     if i.0 {
-    //         for x in i.1 .0..=i.1 .1 {
-    //             for y in i.2 .0..=i.2 .1 {
-    //                 for z in i.3 .0..=i.3 .1 {
-    //                     board.insert((x, y, z));
-    //                 }
-    //             }
-    //         }
-    //     } else {
-    //         for x in i.1 .0..=i.1 .1 {
-    //             for y in i.2 .0..=i.2 .1 {
-    //                 for z in i.3 .0..=i.3 .1 {
-    //                     board.remove(&(x, y, z));
-    //                 }
-    //             }
-    //         }
-    //     }
-    // }
-    // board.len()
-
-    let new_input = input
-        .into_iter()
-        .filter(|i| {
-            i.1 .0 >= -50
-                && i.1 .1 <= 50
-                && i.2 .0 >= -50
-                && i.2 .1 <= 50
-                && i.3 .0 >= -50
-                && i.3 .1 <= 50
-        })
-        .map(|x| *x)
-        .collect::<Vec<(bool, (i32, i32), (i32, i32), (i32, i32))>>();
-    solve_part2(&new_input)
+pub fn solve_part1(input: &[Step]) -> usize {
+    CuboidSet::from_steps(input)
+        .clip(&Cuboid([(-50, 50); 3]))
+        .volume()
 }
 
-type Cuboid = ((i32, i32), (i32, i32), (i32, i32));
-
-pub fn overlaps(fixed: &Cuboid, split: &Cuboid) -> bool {
-    split.0 .0 <= fixed.0 .1
-        && split.0 .1 >= fixed.0 .0
-        && split.1 .0 <= fixed.1 .1
-        && split.1 .1 >= fixed.1 .0
-        && split.2 .0 <= fixed.2 .1
-        && split.2 .1 >= fixed.2 .0
+pub fn overlaps<const N: usize>(fixed: &Cuboid<N>, split: &Cuboid<N>) -> bool {
+    fixed
+        .0
+        .iter()
+        .zip(split.0.iter())
+        .all(|(f, s)| s.0 <= f.1 && s.1 >= f.0)
 }
 
-pub fn nonoverlap(fixed: &Cuboid, split: Cuboid) -> HashSet<Cuboid> {
+pub fn nonoverlap<const N: usize>(fixed: &Cuboid<N>, split: Cuboid<N>) -> HashSet<Cuboid<N>> {
+    let mut out = HashSet::new();
     if overlaps(fixed, &split) {
-        let mut out = HashSet::new();
-
-        assert!(split.0 .0 <= split.0 .1);
-        assert!(split.1 .0 <= split.1 .1);
-        assert!(split.2 .0 <= split.2 .1);
-        assert!(fixed.0 .0 <= fixed.0 .1);
-        assert!(fixed.1 .0 <= fixed.1 .1);
-        assert!(fixed.2 .0 <= fixed.2 .1);
-        // Non-overlapping X ranges (before, after)
-        if split.0 .0 < fixed.0 .0 {
-            out.insert(((split.0 .0, fixed.0 .0 - 1), split.1, split.2));
+        // Peel off the non-overlapping ranges (before, after) one axis at a time, then narrow
+        // the remainder to the overlapping range on that axis before moving to the next
+        let mut rest = split;
+        for axis in 0..N {
+            let (s, f) = (split.0[axis], fixed.0[axis]);
+            assert!(s.0 <= s.1);
+            assert!(f.0 <= f.1);
+            if s.0 < f.0 {
+                let mut before = rest;
+                before.0[axis] = (s.0, f.0 - 1);
+                out.insert(before);
+            }
+            if s.1 > f.1 {
+                let mut after = rest;
+                after.0[axis] = (f.1 + 1, s.1);
+                out.insert(after);
+            }
+            rest.0[axis] = (s.0.max(f.0), s.1.min(f.1));
         }
-        if split.0 .1 > fixed.0 .1 {
-            out.insert(((fixed.0 .1 + 1, split.0 .1), split.1, split.2));
+    } else {
+        out.insert(split);
+    }
+    out
+}
+
+pub fn intersection<const N: usize>(a: &Cuboid<N>, b: &Cuboid<N>) -> Option<Cuboid<N>> {
+    if overlaps(a, b) {
+        let mut out = *a;
+        for (o, r) in out.0.iter_mut().zip(b.0.iter()) {
+            *o = (o.0.max(r.0), o.1.min(r.1));
         }
-        // For overlapping X, get non-overlapping Y ranges
-        let startx = if split.0 .0 >= fixed.0 .0 {
-            split.0 .0
-        } else {
-            fixed.0 .0
-        };
-        let endx = if split.0 .1 <= fixed.0 .1 {
-            split.0 .1
-        } else {
-            fixed.0 .1
-        };
-        if split.1 .0 < fixed.1 .0 {
-            out.insert(((startx, endx), (split.1 .0, fixed.1 .0 - 1), split.2));
+        Some(out)
+    } else {
+        None
+    }
+}
+
+pub fn volume<const N: usize>(c: &Cuboid<N>) -> usize {
+    c.0.iter().map(|r| (1 + r.1 - r.0) as usize).product()
+}
+
+pub fn contains<const N: usize>(c: &Cuboid<N>, p: [i32; N]) -> bool {
+    c.0.iter()
+        .zip(p.iter())
+        .all(|(r, x)| r.0 <= *x && *x <= r.1)
+}
+
+// Set of lit cubes stored as disjoint cuboids, splitting existing ones on every change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CuboidSet<const N: usize = 3> {
+    cuboids: HashSet<Cuboid<N>>,
+}
+
+impl<const N: usize> CuboidSet<N> {
+    pub fn new() -> Self {
+        CuboidSet {
+            cuboids: HashSet::new(),
         }
-        if split.1 .1 > fixed.1 .1 {
-            out.insert(((startx, endx), (fixed.1 .1 + 1, split.1 .1), split.2));
+    }
+
+    pub fn from_steps(steps: &[Step<N>]) -> Self {
+        let mut out = CuboidSet::new();
+        for step in steps {
+            out.apply(step);
         }
-        // For overlapping X,Y get non over-lapping Z ranges
-        let starty = if split.1 .0 >= fixed.1 .0 {
-            split.1 .0
-        } else {
-            fixed.1 .0
-        };
-        let endy = if split.1 .1 <= fixed.1 .1 {
-            split.1 .1
+        out
+    }
+
+    pub fn apply(&mut self, step: &Step<N>) {
+        if step.0 {
+            self.insert(step.1);
         } else {
-            fixed.1 .1
-        };
-        if split.2 .0 < fixed.2 .0 {
-            out.insert(((startx, endx), (starty, endy), (split.2 .0, fixed.2 .0 - 1)));
+            self.remove(&step.1);
         }
-        if split.2 .1 > fixed.2 .1 {
-            out.insert(((startx, endx), (starty, endy), (fixed.2 .1 + 1, split.2 .1)));
+    }
+
+    pub fn insert(&mut self, cuboid: Cuboid<N>) {
+        let mut new: HashSet<Cuboid<N>> = HashSet::new();
+        new.insert(cuboid);
+        for c in self.cuboids.iter() {
+            // Find disjoint non-overlapping and insert
+            new = new.into_iter().flat_map(|n| nonoverlap(c, n)).collect();
         }
+        self.cuboids.extend(new);
+    }
+
+    pub fn remove(&mut self, cuboid: &Cuboid<N>) {
+        // Get non-overlapping parts of existing cuboids only
+        self.cuboids = self
+            .cuboids
+            .drain()
+            .flat_map(|c| nonoverlap(cuboid, c))
+            .collect();
+    }
+
+    pub fn union(&self, other: &CuboidSet<N>) -> CuboidSet<N> {
+        let mut out = self.clone();
+        other.cuboids.iter().for_each(|c| out.insert(*c));
         out
-    } else {
-        let mut out = HashSet::new();
-        out.insert(split);
+    }
+
+    pub fn subtract(&self, other: &CuboidSet<N>) -> CuboidSet<N> {
+        let mut out = self.clone();
+        other.cuboids.iter().for_each(|c| out.remove(c));
         out
     }
+
+    pub fn intersection(&self, other: &CuboidSet<N>) -> CuboidSet<N> {
+        CuboidSet {
+            cuboids: self
+                .cuboids
+                .iter()
+                .flat_map(|a| other.cuboids.iter().filter_map(|b| intersection(a, b)))
+                .collect(),
+        }
+    }
+
+    pub fn clip(&self, region: &Cuboid<N>) -> CuboidSet<N> {
+        CuboidSet {
+            cuboids: self
+                .cuboids
+                .iter()
+                .filter_map(|c| intersection(c, region))
+                .collect(),
+        }
+    }
+
+    pub fn volume(&self) -> usize {
+        self.cuboids.iter().map(volume).sum()
+    }
+
+    pub fn contains(&self, p: [i32; N]) -> bool {
+        self.cuboids.iter().any(|c| contains(c, p))
+    }
+
+    pub fn cuboids(&self) -> impl Iterator<Item = &Cuboid<N>> {
+        self.cuboids.iter()
+    }
 }
 
-#[aoc(day22, part2)]
-pub fn solve_part2(input: &[(bool, (i32, i32), (i32, i32), (i32, i32))]) -> usize {
-    let mut cuboids: HashSet<Cuboid> = HashSet::new(); // Disjoint active cuboids
-
-    for i in input {
-        let mut new: HashSet<Cuboid> = HashSet::new();
-        new.insert((i.1, i.2, i.3));
-
-        if i.0 {
-            for c in cuboids.iter() {
-                // Find disjoint non-overlapping and insert
-                new = new
-                    .into_iter()
-                    .map(|n| nonoverlap(c, n))
-                    .flatten()
-                    .collect::<HashSet<Cuboid>>()
-            }
+impl<const N: usize> Default for CuboidSet<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-            for x in new.into_iter() {
-                cuboids.insert(x);
-            }
+// Set of lit cubes stored as inclusion-exclusion terms: each cuboid carries a signed weight
+// and the weights of all terms covering a cube sum to 1 if it is lit and 0 otherwise
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedCuboidSet<const N: usize = 3> {
+    terms: HashMap<Cuboid<N>, i64>,
+}
+
+impl<const N: usize> SignedCuboidSet<N> {
+    pub fn new() -> Self {
+        SignedCuboidSet {
+            terms: HashMap::new(),
+        }
+    }
+
+    pub fn from_steps(steps: &[Step<N>]) -> Self {
+        let mut out = SignedCuboidSet::new();
+        for step in steps {
+            out.apply(step);
+        }
+        out
+    }
+
+    pub fn apply(&mut self, step: &Step<N>) {
+        if step.0 {
+            self.insert(step.1);
         } else {
-            // Find overlapping ranges and split existing cuboids
-            // i.e. get non-overlapping parts of existing cuboids only
-            cuboids = cuboids
-                .into_iter()
-                .map(|c| nonoverlap(&(i.1, i.2, i.3), c))
-                .flatten()
-                .collect();
+            self.remove(&step.1);
         }
     }
 
-    for c1 in cuboids.iter() {
-        for c2 in cuboids.iter() {
-            if c1 != c2 && overlaps(&c1, &c2) {
-                println!("Cuboids overlap: {:?}, {:?}", &c1, &c2);
+    fn add_term(&mut self, cuboid: Cuboid<N>, weight: i64) {
+        let w = self.terms.entry(cuboid).or_insert(0);
+        *w += weight;
+        if *w == 0 {
+            self.terms.remove(&cuboid);
+        }
+    }
+
+    fn add_all(&mut self, other: &SignedCuboidSet<N>, sign: i64) {
+        other
+            .terms
+            .iter()
+            .for_each(|(c, w)| self.add_term(*c, sign * w));
+    }
+
+    pub fn insert(&mut self, cuboid: Cuboid<N>) {
+        self.remove(&cuboid);
+        self.add_term(cuboid, 1);
+    }
+
+    pub fn remove(&mut self, cuboid: &Cuboid<N>) {
+        // Cancel out everything currently lit inside the cuboid
+        let overlapping: Vec<(Cuboid<N>, i64)> = self
+            .terms
+            .iter()
+            .filter_map(|(c, w)| intersection(c, cuboid).map(|i| (i, *w)))
+            .collect();
+        overlapping
+            .into_iter()
+            .for_each(|(c, w)| self.add_term(c, -w));
+    }
+
+    pub fn union(&self, other: &SignedCuboidSet<N>) -> SignedCuboidSet<N> {
+        let mut out = self.clone();
+        out.add_all(other, 1);
+        out.add_all(&self.intersection(other), -1);
+        out
+    }
+
+    pub fn subtract(&self, other: &SignedCuboidSet<N>) -> SignedCuboidSet<N> {
+        let mut out = self.clone();
+        out.add_all(&self.intersection(other), -1);
+        out
+    }
+
+    pub fn intersection(&self, other: &SignedCuboidSet<N>) -> SignedCuboidSet<N> {
+        let mut out = SignedCuboidSet::new();
+        for (a, wa) in self.terms.iter() {
+            for (b, wb) in other.terms.iter() {
+                if let Some(c) = intersection(a, b) {
+                    out.add_term(c, wa * wb);
+                }
             }
         }
+        out
     }
 
-    cuboids
-        .into_iter()
-        .map(|(x, y, z)| {
-            (1 + x.1 - x.0) as usize * (1 + y.1 - y.0) as usize * (1 + z.1 - z.0) as usize
-        })
-        .sum()
+    pub fn clip(&self, region: &Cuboid<N>) -> SignedCuboidSet<N> {
+        let mut out = SignedCuboidSet::new();
+        self.terms.iter().for_each(|(c, w)| {
+            if let Some(i) = intersection(c, region) {
+                out.add_term(i, *w);
+            }
+        });
+        out
+    }
+
+    pub fn volume(&self) -> usize {
+        self.terms
+            .iter()
+            .map(|(c, w)| volume(c) as i64 * w)
+            .sum::<i64>() as usize
+    }
+
+    pub fn contains(&self, p: [i32; N]) -> bool {
+        self.terms
+            .iter()
+            .filter(|(c, _)| contains(c, p))
+            .map(|(_, w)| w)
+            .sum::<i64>()
+            > 0
+    }
+}
+
+impl<const N: usize> Default for SignedCuboidSet<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepReport {
+    pub lit: usize,
+    // Indices of earlier steps that still decided part of this step's cuboid
+    pub overrides: Vec<usize>,
+}
+
+// Replays reboot steps one at a time, keeping disjoint cuboids tagged with the step that
+// last set them (on or off)
+#[derive(Debug, Clone, Default)]
+pub struct Reboot<const N: usize = 3> {
+    owners: HashMap<Cuboid<N>, usize>,
+    on: Vec<bool>,
+    lit: usize,
+}
+
+impl<const N: usize> Reboot<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(&mut self, step: &Step<N>) -> StepReport {
+        let cuboid = step.1;
+        let mut overrides = HashSet::new();
+        let mut owners = HashMap::new();
+        for (c, owner) in self.owners.drain() {
+            if let Some(i) = intersection(&c, &cuboid) {
+                overrides.insert(owner);
+                if self.on[owner] {
+                    self.lit -= volume(&i);
+                }
+            }
+            owners.extend(nonoverlap(&cuboid, c).into_iter().map(|n| (n, owner)));
+        }
+        owners.insert(cuboid, self.on.len());
+        self.owners = owners;
+        self.on.push(step.0);
+        if step.0 {
+            self.lit += volume(&cuboid);
+        }
+
+        let mut overrides: Vec<usize> = overrides.into_iter().collect();
+        overrides.sort_unstable();
+        StepReport {
+            lit: self.lit,
+            overrides,
+        }
+    }
+
+    pub fn lit(&self) -> usize {
+        self.lit
+    }
+}
+
+pub fn replay<const N: usize>(steps: &[Step<N>]) -> Vec<StepReport> {
+    let mut reboot = Reboot::new();
+    steps.iter().map(|s| reboot.apply(s)).collect()
+}
+
+#[aoc(day22, part2)]
+pub fn solve_part2(input: &[Step]) -> usize {
+    CuboidSet::from_steps(input).volume()
 }
 
 #[cfg(test)]
@@ -205,8 +402,8 @@ on x=-20..33,y=-21..23,z=-26..28";
         assert_eq!(
             input_generator(inp),
             vec![
-                (true, (-20, 26), (-36, 17), (-47, 7)),
-                (true, (-20, 33), (-21, 23), (-26, 28))
+                (true, Cuboid([(-20, 26), (-36, 17), (-47, 7)])),
+                (true, Cuboid([(-20, 33), (-21, 23), (-26, 28)]))
             ]
         );
     }
@@ -301,5 +498,106 @@ on x=-53470..21291,y=-120233..-33476,z=-44150..38147
 off x=-93533..-4276,y=-16170..68771,z=-104985..-24507";
         let parsed = input_generator(inp);
         assert_eq!(solve_part2(&parsed), 2758514936282235);
+        assert_eq!(
+            SignedCuboidSet::from_steps(&parsed).volume(),
+            2758514936282235
+        );
+        assert_eq!(replay(&parsed).last().unwrap().lit, 2758514936282235);
+    }
+    #[test]
+    fn test_signed_matches_split() {
+        let inp = "on x=10..12,y=10..12,z=10..12
+on x=11..13,y=11..13,z=11..13
+off x=9..11,y=9..11,z=9..11
+on x=10..10,y=10..10,z=10..10";
+        let parsed = input_generator(inp);
+        assert_eq!(CuboidSet::from_steps(&parsed).volume(), 39);
+        assert_eq!(SignedCuboidSet::from_steps(&parsed).volume(), 39);
+        assert!(CuboidSet::from_steps(&parsed).contains([10, 10, 10]));
+        assert!(!CuboidSet::from_steps(&parsed).contains([11, 10, 10]));
+        assert!(SignedCuboidSet::from_steps(&parsed).contains([10, 10, 10]));
+        assert!(!SignedCuboidSet::from_steps(&parsed).contains([11, 10, 10]));
+    }
+    #[test]
+    fn test_set_operations() {
+        let mut a = CuboidSet::new();
+        a.insert(Cuboid([(0, 9), (0, 9), (0, 9)]));
+        let mut b = CuboidSet::new();
+        b.insert(Cuboid([(5, 14), (0, 9), (0, 9)]));
+        assert_eq!(a.union(&b).volume(), 1500);
+        assert_eq!(a.intersection(&b).volume(), 500);
+        assert_eq!(a.subtract(&b).volume(), 500);
+        assert_eq!(a.clip(&Cuboid([(-5, 4); 3])).volume(), 125);
+
+        let mut sa = SignedCuboidSet::new();
+        sa.insert(Cuboid([(0, 9), (0, 9), (0, 9)]));
+        let mut sb = SignedCuboidSet::new();
+        sb.insert(Cuboid([(5, 14), (0, 9), (0, 9)]));
+        assert_eq!(sa.union(&sb).volume(), 1500);
+        assert_eq!(sa.intersection(&sb).volume(), 500);
+        assert_eq!(sa.subtract(&sb).volume(), 500);
+        assert_eq!(sa.clip(&Cuboid([(-5, 4); 3])).volume(), 125);
+        assert!(sa.subtract(&sb).contains([4, 9, 9]));
+        assert!(!sa.subtract(&sb).contains([5, 9, 9]));
+    }
+    #[test]
+    fn test_replay() {
+        let inp = "on x=10..12,y=10..12,z=10..12
+on x=11..13,y=11..13,z=11..13
+off x=9..11,y=9..11,z=9..11
+on x=10..10,y=10..10,z=10..10";
+        let parsed = input_generator(inp);
+        assert_eq!(
+            replay(&parsed),
+            vec![
+                StepReport {
+                    lit: 27,
+                    overrides: vec![]
+                },
+                StepReport {
+                    lit: 46,
+                    overrides: vec![0]
+                },
+                StepReport {
+                    lit: 38,
+                    overrides: vec![0, 1]
+                },
+                StepReport {
+                    lit: 39,
+                    overrides: vec![2]
+                },
+            ]
+        );
+    }
+    #[test]
+    fn test_2d() {
+        let inp = "on x=0..9,y=0..9
+on x=5..14,y=5..14
+off x=0..4,y=0..4";
+        let parsed = parse_steps::<2>(inp);
+        assert_eq!(CuboidSet::from_steps(&parsed).volume(), 150);
+        assert_eq!(SignedCuboidSet::from_steps(&parsed).volume(), 150);
+        assert_eq!(replay(&parsed).last().unwrap().lit, 150);
+        assert!(!CuboidSet::from_steps(&parsed).contains([4, 4]));
+    }
+    #[test]
+    fn test_4d() {
+        let inp = "on x=0..9,y=0..9,z=0..9,w=0..9
+off x=0..9,y=0..9,z=0..9,w=5..5
+on x=0..0,y=0..0,z=0..0,w=5..14";
+        let parsed = parse_steps::<4>(inp);
+        assert_eq!(CuboidSet::from_steps(&parsed).volume(), 9006);
+        assert_eq!(SignedCuboidSet::from_steps(&parsed).volume(), 9006);
+        assert_eq!(replay(&parsed).last().unwrap().lit, 9006);
+    }
+    #[test]
+    #[should_panic(expected = "neither on nor off: \"of x=1..2,y=1..2,z=1..2\"")]
+    fn test_parse_bad_state() {
+        input_generator("on x=1..2,y=1..2,z=1..2\nof x=1..2,y=1..2,z=1..2");
+    }
+    #[test]
+    #[should_panic(expected = "neither on nor off: \"\"")]
+    fn test_parse_blank_line() {
+        input_generator("on x=1..2,y=1..2,z=1..2\n\noff x=1..2,y=1..2,z=1..2");
     }
 }
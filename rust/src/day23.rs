@@ -1,6 +1,7 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use regex::Regex;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Amphipod {
@@ -30,6 +31,16 @@ impl Amphipod {
             D => 1000,
         }
     }
+    pub fn from_index(i: usize) -> Amphipod {
+        use Amphipod::*;
+        match i {
+            0 => A,
+            1 => B,
+            2 => C,
+            3 => D,
+            _ => panic!("bad index"),
+        }
+    }
     pub fn index(&self) -> usize {
         use Amphipod::*;
         match self {
@@ -121,193 +132,176 @@ pub fn room_to_room_cost(room1: usize, room2: usize) -> usize {
     }
 }
 
+// Each cell takes 3 bits: 0 is empty, otherwise Amphipod::index() + 1
+// Halls are cells 0..7, slot s (counted from the bottom) of room r is cell 7 + 4 * r + s
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PackedState(u128);
+
+impl PackedState {
+    pub fn pack(state: &State) -> Self {
+        let (rooms, halls) = state;
+        let mut out = PackedState(0);
+        halls.iter().enumerate().for_each(|(i, h)| out.set(i, *h));
+        rooms.iter().enumerate().for_each(|(r, room)| {
+            room.iter()
+                .enumerate()
+                .for_each(|(slot, x)| out.set(room_cell(r, slot), Some(*x)))
+        });
+        out
+    }
+
+    pub fn unpack(&self) -> State {
+        let mut rooms: [Vec<Amphipod>; 4] = [vec![], vec![], vec![], vec![]];
+        let mut halls: [Option<Amphipod>; 7] = [None; 7];
+        (0..7).for_each(|i| halls[i] = self.get(i));
+        (0..4).for_each(|r| rooms[r] = (0..4).map_while(|slot| self.room(r, slot)).collect());
+        (rooms, halls)
+    }
+
+    pub fn get(&self, cell: usize) -> Option<Amphipod> {
+        match (self.0 >> (3 * cell)) & 0b111 {
+            0 => None,
+            x => Some(Amphipod::from_index(x as usize - 1)),
+        }
+    }
+
+    pub fn set(&mut self, cell: usize, x: Option<Amphipod>) {
+        let v = x.map(|a| a.index() as u128 + 1).unwrap_or(0);
+        self.0 = (self.0 & !(0b111 << (3 * cell))) | (v << (3 * cell));
+    }
+
+    pub fn hall(&self, i: usize) -> Option<Amphipod> {
+        self.get(i)
+    }
+
+    pub fn room(&self, r: usize, slot: usize) -> Option<Amphipod> {
+        self.get(room_cell(r, slot))
+    }
+
+    pub fn room_len(&self, r: usize) -> usize {
+        (0..4)
+            .take_while(|&slot| self.room(r, slot).is_some())
+            .count()
+    }
+
+    // Room only holds amphipods that belong there
+    pub fn room_settled(&self, r: usize) -> bool {
+        (0..self.room_len(r)).all(|slot| self.room(r, slot).map(|x| x.index()) == Some(r))
+    }
+
+    pub fn halls_free(&self, mut halls: impl Iterator<Item = usize>) -> bool {
+        halls.all(|h| self.hall(h).is_none())
+    }
+}
+
+pub fn room_cell(r: usize, slot: usize) -> usize {
+    7 + 4 * r + slot
+}
+
 // Can only ever move to destination or not from any given hall
-pub fn new_hall_states(
-    state: &State,
-    mincost: &mut HashMap<State, usize>,
-    best_from: &mut HashMap<State, State>,
-    room_size: usize,
-) -> Vec<State> {
-    let (rooms, halls) = state;
-    halls
-        .iter()
-        .enumerate()
-        .map(|(i, h)| {
-            if let Some(&x) = h.as_ref() {
-                let dest = x.index();
-                // Is dest free?
-                let dest_free = (rooms[dest].len() == 0)
-                    || (rooms[dest].iter().all(|x| x.index() == dest)
-                        && rooms[dest].len() < room_size);
-                // Are halls to dest free?
-                let halls_free = (if i > (dest + 1) {
-                    dest + 2..=i
-                } else {
-                    i + 1..=dest + 1
-                })
-                .all(|z| if z == i { true } else { halls[z].is_none() });
-
-                if dest_free && halls_free {
-                    // Calculate movement cost
-                    let new_cost = x.move_cost()
-                        * (hall_to_room_cost(i, dest)
-                            + (room_size - rooms[dest].len()));
-                    let cost = mincost.get(&state).expect("mincost missing") + new_cost;
-                    let mut new_halls = halls.clone();
-                    let to_move = new_halls[i].take().unwrap(); // x
-                    let mut new_rooms = rooms.clone();
-                    new_rooms[dest].push(to_move);
-                    let new_state = (new_rooms, new_halls);
-                    if mincost.get(&new_state).map(|x| *x).unwrap_or(usize::MAX) > cost {
-                        // Update minimum cost map
-                        // Update min cost from map
-                        mincost
-                            .entry(new_state.clone())
-                            .and_modify(|v| *v = cost)
-                            .or_insert(cost);
-                        best_from
-                            .entry(new_state.clone())
-                            .and_modify(|v| *v = state.clone())
-                            .or_insert(state.clone());
-                        return Some(new_state);
-                    }
-                }
-                None
+pub fn hall_moves(state: PackedState, room_size: usize) -> Vec<(PackedState, usize)> {
+    (0..7)
+        .filter_map(|i| {
+            let x = state.hall(i)?;
+            let dest = x.index();
+            let dest_len = state.room_len(dest);
+            // Is dest free?
+            let dest_free = state.room_settled(dest) && dest_len < room_size;
+            // Are halls to dest free?
+            let halls_free = if i > (dest + 1) {
+                state.halls_free(dest + 2..i)
             } else {
-                None
+                state.halls_free(i + 1..=dest + 1)
+            };
+            if !(dest_free && halls_free) {
+                return None;
             }
+            let cost = x.move_cost() * (hall_to_room_cost(i, dest) + (room_size - dest_len));
+            let mut new_state = state;
+            new_state.set(i, None);
+            new_state.set(room_cell(dest, dest_len), Some(x));
+            Some((new_state, cost))
         })
-        .filter_map(|x| x)
         .collect()
 }
 
 // Can move to destination or to any reachable hall
-// If 2 elements in room only outer one can leave
-pub fn new_room_states(
-    state: &State,
-    mincost: &mut HashMap<State, usize>,
-    best_from: &mut HashMap<State, State>,
-    room_size: usize,
-) -> Vec<State> {
-    let (rooms, halls) = state;
+// Only the amphipod nearest the hallway can leave a room
+pub fn room_moves(state: PackedState, room_size: usize) -> Vec<(PackedState, usize)> {
+    let mut out = Vec::new();
+    for i in 0..4 {
+        // Do not move out of room if room solved
+        if state.room_settled(i) {
+            continue;
+        }
+        let len = state.room_len(i);
+        let x = state.room(i, len - 1).unwrap();
+        let up_cost = 1 + room_size - len; // Vertical move up
+        let mut left_room = state;
+        left_room.set(room_cell(i, len - 1), None);
+
+        let dest = x.index();
+        let dest_len = state.room_len(dest);
+        let dest_free = dest != i && state.room_settled(dest) && dest_len < room_size;
+        let halls_free = if i > dest {
+            state.halls_free(dest + 2..=i + 1)
+        } else {
+            state.halls_free(i + 2..=dest + 1)
+        };
+        if dest_free && halls_free {
+            // Going straight home always beats stopping in the hall
+            let cost =
+                x.move_cost() * (room_to_room_cost(i, dest) + (room_size - dest_len) + up_cost);
+            let mut new_state = left_room;
+            new_state.set(room_cell(dest, dest_len), Some(x));
+            out.push((new_state, cost));
+            continue;
+        }
 
-    let mut room_to_room: Vec<State> = rooms
-        .iter()
-        .enumerate()
-        .map(|(i, r)| {
-            if r.len() > 0 {
-                let x = r[r.len() - 1];
-                let dest = x.index();
-                // Is dest free?
-                let mut dest_free = (rooms[dest].len() == 0)
-                    || (rooms[dest].iter().all(|x| x.index() == dest)
-                        && rooms[dest].len() < room_size);
-                // Do not try to move to dest if already in dest
-                if dest == i {
-                    dest_free = false;
-                }
-                // Are halls to dest free?
-                let halls_free = if i > dest {
-                    dest + 2..=i + 1
-                } else {
-                    i + 2..=dest + 1
-                }
-                .all(|z| halls[z].is_none());
-
-                if dest_free && halls_free {
-                    // Calculate movement cost
-                    let new_cost = x.move_cost()
-                        * (room_to_room_cost(i, dest) 
-                            + (room_size - rooms[dest].len())  // Vertical move down
-                            + (1 + room_size - r.len())); // Vertical move up
-                    let cost = mincost.get(&state).expect("mincost missing") + new_cost;
-                    let mut new_halls = halls.clone();
-                    let mut new_rooms = rooms.clone();
-                    let to_move = new_rooms[i].pop().unwrap();
-                    new_rooms[dest].push(to_move);
-                    let new_state = (new_rooms, new_halls);
-                    if mincost.get(&new_state).map(|x| *x).unwrap_or(usize::MAX) > cost {
-                        // Update minimum cost map
-                        // Update min cost from map
-                        mincost
-                            .entry(new_state.clone())
-                            .and_modify(|v| *v = cost)
-                            .or_insert(cost);
-                        best_from
-                            .entry(new_state.clone())
-                            .and_modify(|v| *v = state.clone())
-                            .or_insert(state.clone());
-                        return Some(new_state);
-                    }
-                }
-                None
+        for dh in 0..7 {
+            let halls_free = if (i + 1) >= dh {
+                state.halls_free(dh..=i + 1)
             } else {
-                None
+                state.halls_free(i + 2..=dh)
+            };
+            if halls_free {
+                let cost = x.move_cost() * (hall_to_room_cost(dh, i) + up_cost);
+                let mut new_state = left_room;
+                new_state.set(dh, Some(x));
+                out.push((new_state, cost));
             }
-        })
-        .filter_map(|x| x)
-        .collect();
-
-    let mut room_to_halls: Vec<State> = rooms
-        .iter()
-        .enumerate()
-        .map(|(i, r)| {
-            // Do not move out of room if room solved
-            if r.iter().all(|x| x.index() == i) {
-                return vec![];
-            }
-            if r.len() == 0 {
-                return vec![];
-            }
-
-            (0..(halls.len()))
-                .map(|dh| {
-                    let x = r[r.len() - 1];
-                    // Is dest free?
-                    let dest_free = halls[dh].is_none();
-                    // Are halls to dest free?
-                    let halls_free = if (i + 1) >= dh {
-                        dh..=i + 1
-                    } else {
-                        i + 2..=dh
-                    }
-                    .all(|z| halls[z].is_none());
-
-                    if dest_free && halls_free {
-                        // Calculate movement cost
-                        let new_cost = x.move_cost()
-                            * (hall_to_room_cost(dh, i) + (1+ room_size - r.len()) ); // Vertical move up
-                        let cost = mincost.get(&state).expect("mincost missing") + new_cost;
-                        let mut new_halls = halls.clone();
-                        let mut new_rooms = rooms.clone();
-                        let to_move = new_rooms[i].pop().unwrap();
-                        new_halls[dh] = Some(to_move);
-
-                        let new_state = (new_rooms, new_halls);
-                        if mincost.get(&new_state).map(|x| *x).unwrap_or(usize::MAX) > cost {
-                            // Update minimum cost map
-                            // Update min cost from map
-                            mincost
-                                .entry(new_state.clone())
-                                .and_modify(|v| *v = cost)
-                                .or_insert(cost);
-                            best_from
-                                .entry(new_state.clone())
-                                .and_modify(|v| *v = state.clone())
-                                .or_insert(state.clone());
-                            return Some(new_state);
-                        }
-                    }
-                    None
-                })
-                .collect()
-        })
-        .flatten()
-        .filter_map(|x| x)
-        .collect();
+        }
+    }
+    out
+}
 
-    room_to_room.append(&mut room_to_halls);
-    room_to_room
+// Lower bound on the energy still needed: every amphipod not yet home walks straight to
+// the hallway above its room, then the room fills from the bottom up
+pub fn heuristic(state: PackedState, room_size: usize) -> usize {
+    let mut total = 0;
+    for i in 0..7 {
+        if let Some(x) = state.hall(i) {
+            total += x.move_cost() * hall_to_room_cost(i, x.index());
+        }
+    }
+    for r in 0..4 {
+        // Amphipods at the bottom that already belong here never move again
+        let home = (0..state.room_len(r))
+            .take_while(|&slot| state.room(r, slot).map(|x| x.index()) == Some(r))
+            .count();
+        for slot in home..state.room_len(r) {
+            let x = state.room(r, slot).unwrap();
+            let across = if x.index() == r {
+                2
+            } else {
+                room_to_room_cost(r, x.index())
+            };
+            total += x.move_cost() * (room_size - slot + across);
+        }
+        let enter: usize = (home..room_size).map(|slot| room_size - slot).sum();
+        total += Amphipod::from_index(r).move_cost() * enter;
+    }
+    total
 }
 
 pub fn p1_to_p2(s: &State) -> State {
@@ -331,67 +325,195 @@ pub fn p1_to_p2(s: &State) -> State {
     news.0[3].push(A);
     news.0[3].push(last_val);
     news
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Hall(usize),
+    // Room index and depth below the hallway (0 is the slot next to the hallway)
+    Room(usize, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub amphipod: Amphipod,
+    pub from: Location,
+    pub to: Location,
+    pub cost: usize,
+}
+
+pub fn solved_state(room_size: usize) -> State {
+    use Amphipod::*;
+    (
+        [
+            vec![A; room_size],
+            vec![B; room_size],
+            vec![C; room_size],
+            vec![D; room_size],
+        ],
+        [None; 7],
+    )
+}
+
+// A* over packed states, returning each state on the cheapest path with its cost so far
+pub fn search(start: &State, room_size: usize) -> Option<Vec<(State, usize)>> {
+    let start = PackedState::pack(start);
+    let solved = PackedState::pack(&solved_state(room_size));
+    let mut mincost: HashMap<PackedState, usize> = HashMap::new();
+    let mut best_from: HashMap<PackedState, PackedState> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+
+    mincost.insert(start, 0);
+    queue.push(Reverse((heuristic(start, room_size), 0, start)));
+
+    while let Some(Reverse((_, cost, s))) = queue.pop() {
+        if s == solved {
+            let mut path = vec![(s.unpack(), cost)];
+            let mut cur = s;
+            while let Some(prev) = best_from.get(&cur) {
+                path.push((prev.unpack(), mincost[prev]));
+                cur = *prev;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        // Skip queue entries superseded by a cheaper route
+        if cost > mincost[&s] {
+            continue;
+        }
+        for (next, move_cost) in hall_moves(s, room_size)
+            .into_iter()
+            .chain(room_moves(s, room_size))
+        {
+            let new_cost = cost + move_cost;
+            if !matches!(mincost.get(&next), Some(&c) if c <= new_cost) {
+                mincost.insert(next, new_cost);
+                best_from.insert(next, s);
+                queue.push(Reverse((
+                    new_cost + heuristic(next, room_size),
+                    new_cost,
+                    next,
+                )));
+            }
+        }
+    }
+    None
+}
+
+// Work out which single move takes prev to next
+pub fn find_move(prev: &State, next: &State, room_size: usize, cost: usize) -> Move {
+    let mut from = None;
+    let mut to = None;
+    let mut amphipod = None;
+    for i in 0..prev.1.len() {
+        match (prev.1[i], next.1[i]) {
+            (Some(x), None) => {
+                from = Some(Location::Hall(i));
+                amphipod = Some(x);
+            }
+            (None, Some(x)) => {
+                to = Some(Location::Hall(i));
+                amphipod = Some(x);
+            }
+            _ => {}
+        }
+    }
+    for i in 0..prev.0.len() {
+        let (before, after) = (&prev.0[i], &next.0[i]);
+        if after.len() < before.len() {
+            from = Some(Location::Room(i, room_size - before.len()));
+            amphipod = before.last().copied();
+        } else if after.len() > before.len() {
+            to = Some(Location::Room(i, room_size - after.len()));
+            amphipod = after.last().copied();
+        }
+    }
+    Move {
+        amphipod: amphipod.expect("no amphipod moved"),
+        from: from.expect("no move source"),
+        to: to.expect("no move destination"),
+        cost,
+    }
+}
+
+pub fn solve_moves(start: &State, room_size: usize) -> (usize, Vec<Move>) {
+    let path = search(start, room_size).expect("No solution");
+    let moves = path
+        .windows(2)
+        .map(|w| find_move(&w[0].0, &w[1].0, room_size, w[1].1 - w[0].1))
+        .collect();
+    (path.last().unwrap().1, moves)
+}
+
+pub fn apply_move(state: &State, m: &Move) -> State {
+    let mut news = state.clone();
+    let x = match m.from {
+        Location::Hall(i) => news.1[i].take(),
+        Location::Room(i, _) => news.0[i].pop(),
+    }
+    .expect("no amphipod at move source");
+    assert_eq!(x, m.amphipod);
+    match m.to {
+        Location::Hall(i) => news.1[i] = Some(x),
+        Location::Room(i, _) => news.0[i].push(x),
+    }
+    news
+}
 
+pub fn render(state: &State, room_size: usize) -> String {
+    let (rooms, halls) = state;
+    let to_char = |x: Option<&Amphipod>| match x {
+        Some(a) => format!("{:?}", a),
+        None => ".".to_string(),
+    };
+
+    let mut hallway = vec![".".to_string(); 11];
+    halls
+        .iter()
+        .enumerate()
+        .for_each(|(i, h)| hallway[hall_to_x(i)] = to_char(h.as_ref()));
+
+    let mut out = vec![
+        "#############".to_string(),
+        format!("#{}#", hallway.concat()),
+    ];
+    for depth in 0..room_size {
+        let cells: Vec<String> = rooms
+            .iter()
+            .map(|r| to_char(r.get(room_size - 1 - depth)))
+            .collect();
+        if depth == 0 {
+            out.push(format!("###{}###", cells.join("#")));
+        } else {
+            out.push(format!("  #{}#", cells.join("#")));
+        }
+    }
+    out.push("  #########".to_string());
+    out.join("\n")
+}
+
+pub fn render_moves(start: &State, moves: &[Move], room_size: usize) -> String {
+    let mut state = start.clone();
+    let mut out = vec![render(&state, room_size)];
+    for m in moves {
+        state = apply_move(&state, m);
+        out.push(format!(
+            "{:?} moves {:?} -> {:?} (cost {})",
+            m.amphipod, m.from, m.to, m.cost
+        ));
+        out.push(render(&state, room_size));
+    }
+    out.join("\n\n")
 }
 
 #[aoc(day23, part1)]
 pub fn solve_part1(input: &State) -> usize {
-    use Amphipod::*;
-    let mut state = input.clone();
-    let room_size: usize = 2;
-
-    let mut mincost: HashMap<State, usize> = HashMap::new();
-    let mut best_from: HashMap<State, State> = HashMap::new();
-    let solved_state = (
-        [vec![A, A], vec![B, B], vec![C, C], vec![D, D]],
-        [None, None, None, None, None, None, None],
-    );
-
-    mincost.insert(state.clone(), 0);
-
-    let mut pos_states: Vec<State> = vec![state.clone()];
-
-    while !pos_states.is_empty() {
-        // For possible moves, first check any in halls - can they move to destination (they can
-        // never move to hall from hall)
-        let s = pos_states.pop().unwrap();
-        let mut hall_states: Vec<State> = new_hall_states(&s, &mut mincost, &mut best_from, room_size);
-        // Then any in rooms - can they leave and enter hall or destination
-        let mut room_states: Vec<State> = new_room_states(&s, &mut mincost, &mut best_from, room_size);
-        pos_states.append(&mut room_states);
-        pos_states.append(&mut hall_states);
-    }
-    *mincost.get(&solved_state).expect("No solution")
+    solve_moves(input, 2).0
 }
 
 #[aoc(day23, part2)]
 pub fn solve_part2(input: &State) -> usize {
-    use Amphipod::*;
-    let mut state = p1_to_p2(input);
-    let room_size: usize = 4;
-
-    let mut mincost: HashMap<State, usize> = HashMap::new();
-    let mut best_from: HashMap<State, State> = HashMap::new();
-    let solved_state = (
-        [vec![A, A,A,A], vec![B, B,B,B], vec![C, C,C,C], vec![D, D,D,D]],
-        [None, None, None, None, None, None, None],
-    );
-
-    mincost.insert(state.clone(), 0);
-
-    let mut pos_states: Vec<State> = vec![state.clone()];
-
-    while !pos_states.is_empty() {
-        // For possible moves, first check any in halls - can they move to destination (they can
-        // never move to hall from hall)
-        let s = pos_states.pop().unwrap();
-        let mut hall_states: Vec<State> = new_hall_states(&s, &mut mincost, &mut best_from, room_size);
-        // Then any in rooms - can they leave and enter hall or destination
-        let mut room_states: Vec<State> = new_room_states(&s, &mut mincost, &mut best_from, room_size);
-        pos_states.append(&mut room_states);
-        pos_states.append(&mut hall_states);
-    }
-    *mincost.get(&solved_state).expect("No solution")
+    solve_moves(&p1_to_p2(input), 4).0
 }
 
 #[cfg(test)]
@@ -399,6 +521,10 @@ mod tests {
     use super::*;
     use Amphipod::*;
 
+    fn unpacked(moves: Vec<(PackedState, usize)>) -> Vec<(State, usize)> {
+        moves.into_iter().map(|(s, c)| (s.unpack(), c)).collect()
+    }
+
     #[test]
     fn test_parse() {
         let inp = "#############
@@ -460,13 +586,9 @@ mod tests {
   #A#D#C#D#
   #########";
         let state = input_generator(&inp);
-        let mut mincost: HashMap<State, usize> = HashMap::new();
-        let mut best_from: HashMap<State, State> = HashMap::new();
-        mincost.insert(state.clone(), 0);
-        let hall_states: Vec<State> = new_hall_states(&state, &mut mincost, &mut best_from, 2);
+        let hall_states = unpacked(hall_moves(PackedState::pack(&state), 2));
         let new_state = ([vec![A, A], vec![D, C], vec![C, B], vec![D, B]], [None; 7]);
-        assert_eq!(hall_states, vec![new_state.clone()]);
-        assert_eq!(*mincost.get(&new_state).unwrap(), 8);
+        assert_eq!(hall_states, vec![(new_state, 8)]);
     }
     #[test]
     fn test_hallmove2() {
@@ -476,10 +598,7 @@ mod tests {
   #A#B#C#D#
   #########";
         let state = input_generator(&inp);
-        let mut mincost: HashMap<State, usize> = HashMap::new();
-        let mut best_from: HashMap<State, State> = HashMap::new();
-        mincost.insert(state.clone(), 0);
-        let hall_states: Vec<State> = new_hall_states(&state, &mut mincost, &mut best_from, 2);
+        let hall_states = unpacked(hall_moves(PackedState::pack(&state), 2));
         let new_state1 = (
             [vec![A, A], vec![B, D], vec![C], vec![D, B]],
             [None, None, None, None, Some(C), None, None],
@@ -488,9 +607,7 @@ mod tests {
             [vec![A], vec![B, D], vec![C, C], vec![D, B]],
             [None, None, None, Some(A), None, None, None],
         );
-        assert_eq!(hall_states, vec![new_state1.clone(), new_state2.clone()]);
-        assert_eq!(*mincost.get(&new_state1).unwrap(), 4);
-        assert_eq!(*mincost.get(&new_state2).unwrap(), 200);
+        assert_eq!(hall_states, vec![(new_state1, 4), (new_state2, 200)]);
     }
     #[test]
     fn test_hallmove3() {
@@ -500,10 +617,7 @@ mod tests {
   #A#B#C#D#
   #########";
         let state = input_generator(&inp);
-        let mut mincost: HashMap<State, usize> = HashMap::new();
-        let mut best_from: HashMap<State, State> = HashMap::new();
-        mincost.insert(state.clone(), 0);
-        let hall_states: Vec<State> = new_hall_states(&state, &mut mincost, &mut best_from, 2);
+        let hall_states = unpacked(hall_moves(PackedState::pack(&state), 2));
         assert_eq!(hall_states, vec![]);
     }
     #[test]
@@ -514,10 +628,7 @@ mod tests {
   #A#B#C#D#
   #########";
         let state = input_generator(&inp);
-        let mut mincost: HashMap<State, usize> = HashMap::new();
-        let mut best_from: HashMap<State, State> = HashMap::new();
-        mincost.insert(state.clone(), 0);
-        let hall_states: Vec<State> = new_hall_states(&state, &mut mincost, &mut best_from, 2);
+        let hall_states = unpacked(hall_moves(PackedState::pack(&state), 2));
         assert_eq!(hall_states, vec![]);
 
         let new_state1 = (
@@ -525,9 +636,8 @@ mod tests {
             [Some(A), None, None, None, None, None, None],
         );
 
-        let room_states: Vec<State> = new_room_states(&state, &mut mincost, &mut best_from, 2);
-        assert!(room_states.contains(&new_state1));
-        assert_eq!(*mincost.get(&new_state1).unwrap(), 8000);
+        let room_states = unpacked(room_moves(PackedState::pack(&state), 2));
+        assert!(room_states.contains(&(new_state1, 8000)));
     }
     #[test]
     fn test_roomhallmove1() {
@@ -537,10 +647,7 @@ mod tests {
   #D#B#.#D#
   #########";
         let state = input_generator(&inp);
-        let mut mincost: HashMap<State, usize> = HashMap::new();
-        let mut best_from: HashMap<State, State> = HashMap::new();
-        mincost.insert(state.clone(), 0);
-        let hall_states: Vec<State> = new_hall_states(&state, &mut mincost, &mut best_from, 2);
+        let hall_states = unpacked(hall_moves(PackedState::pack(&state), 2));
         assert_eq!(hall_states, vec![]);
 
         let new_state1 = (
@@ -548,9 +655,8 @@ mod tests {
             [Some(A), Some(D), Some(C), Some(A), Some(B), Some(C), None],
         );
 
-        let room_states: Vec<State> = new_room_states(&state, &mut mincost, &mut best_from, 2);
-        assert_eq!(room_states, vec![new_state1.clone()]);
-        assert_eq!(*mincost.get(&new_state1).unwrap(), 3000);
+        let room_states = unpacked(room_moves(PackedState::pack(&state), 2));
+        assert_eq!(room_states, vec![(new_state1, 3000)]);
     }
     #[test]
     fn test_roommove2() {
@@ -560,10 +666,7 @@ mod tests {
   #A#B#C#.#
   #########";
         let state = input_generator(&inp);
-        let mut mincost: HashMap<State, usize> = HashMap::new();
-        let mut best_from: HashMap<State, State> = HashMap::new();
-        mincost.insert(state.clone(), 0);
-        let hall_states: Vec<State> = new_hall_states(&state, &mut mincost, &mut best_from, 2);
+        let hall_states = unpacked(hall_moves(PackedState::pack(&state), 2));
         assert_eq!(hall_states, vec![]);
 
         let new_state1 = (
@@ -571,9 +674,8 @@ mod tests {
             [Some(D), Some(A), None, None, None, None, None],
         );
 
-        let room_states: Vec<State> = new_room_states(&state, &mut mincost, &mut best_from, 2);
-        assert!(room_states.contains(&new_state1));
-        assert_eq!(*mincost.get(&new_state1).unwrap(), 9000);
+        let room_states = unpacked(room_moves(PackedState::pack(&state), 2));
+        assert!(room_states.contains(&(new_state1, 9000)));
     }
     fn test_roomhallmove2() {
         let inp = "#############
@@ -588,15 +690,11 @@ mod tests {
   #########";
         let state = input_generator(&inp);
         let state2 = input_generator(&out);
-        let mut mincost: HashMap<State, usize> = HashMap::new();
-        let mut best_from: HashMap<State, State> = HashMap::new();
-        mincost.insert(state.clone(), 0);
-        let hall_states: Vec<State> = new_hall_states(&state, &mut mincost, &mut best_from, 2);
+        let hall_states = unpacked(hall_moves(PackedState::pack(&state), 2));
         assert_eq!(hall_states, vec![]);
 
-        let room_states: Vec<State> = new_room_states(&state, &mut mincost, &mut best_from, 2);
-        assert!(room_states.contains(&state2));
-        assert_eq!(*mincost.get(&state2).unwrap(), 40);
+        let room_states = unpacked(room_moves(PackedState::pack(&state), 2));
+        assert!(room_states.contains(&(state2, 40)));
     }
     #[test]
     fn test_part1() {
@@ -618,4 +716,81 @@ mod tests {
         let state = input_generator(&inp);
         assert_eq!(solve_part2(&state), 44169);
     }
+    #[test]
+    fn test_render() {
+        let inp = "#############
+#.....A.C...#
+###.#D#.#B###
+  #A#B#C#D#
+  #########";
+        let state = input_generator(&inp);
+        assert_eq!(render(&state, 2), inp);
+    }
+    #[test]
+    fn test_solve_moves() {
+        let inp = "#############
+#...........#
+###B#C#B#D###
+  #A#D#C#A#
+  #########";
+        let state = input_generator(&inp);
+        let (total, moves) = solve_moves(&state, 2);
+        assert_eq!(total, 12521);
+        assert_eq!(moves.iter().map(|m| m.cost).sum::<usize>(), 12521);
+        let end = moves.iter().fold(state.clone(), |s, m| apply_move(&s, m));
+        assert_eq!(end, solved_state(2));
+        assert!(render_moves(&state, &moves, 2).ends_with(
+            "#############
+#...........#
+###A#B#C#D###
+  #A#B#C#D#
+  #########"
+        ));
+    }
+    #[test]
+    fn test_solve_moves_part2() {
+        let inp = "#############
+#...........#
+###B#C#B#D###
+  #A#D#C#A#
+  #########";
+        let state = p1_to_p2(&input_generator(&inp));
+        let (total, moves) = solve_moves(&state, 4);
+        assert_eq!(total, 44169);
+        assert_eq!(moves.iter().map(|m| m.cost).sum::<usize>(), 44169);
+        assert_eq!(moves[0].amphipod, Amphipod::D);
+        assert_eq!(moves[0].from, Location::Room(3, 0));
+        assert_eq!(moves[0].to, Location::Hall(6));
+    }
+    #[test]
+    fn test_pack() {
+        let inp = "#############
+#A..C.A.B.C.#
+###.#.#.#.###
+  #D#B#.#D#
+  #########";
+        let state = input_generator(&inp);
+        assert_eq!(PackedState::pack(&state).unpack(), state);
+        let state2 = p1_to_p2(&input_generator(
+            "#############
+#...........#
+###B#C#B#D###
+  #A#D#C#A#
+  #########",
+        ));
+        assert_eq!(PackedState::pack(&state2).unpack(), state2);
+    }
+    #[test]
+    fn test_heuristic() {
+        let inp = "#############
+#...........#
+###B#C#B#D###
+  #A#D#C#A#
+  #########";
+        let state = input_generator(&inp);
+        assert_eq!(heuristic(PackedState::pack(&solved_state(2)), 2), 0);
+        assert_eq!(heuristic(PackedState::pack(&solved_state(4)), 4), 0);
+        assert!(heuristic(PackedState::pack(&state), 2) <= 12521);
+        assert!(heuristic(PackedState::pack(&p1_to_p2(&state)), 4) <= 44169);
+    }
 }